@@ -0,0 +1,61 @@
+use std::hint::black_box;
+
+use accountant_tool::config::Config;
+use accountant_tool::{storage, tax};
+use chrono::NaiveDate;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// A year of `count` invoices in the file format, spread evenly from January 2026 on
+fn year_file(count: usize) -> String {
+    let start: u128 = 1_767_268_800_000;
+    let step: u128 = 365 * 24 * 60 * 60 * 1000 / count as u128;
+    let mut file = String::from(
+        "name,date,days_worked,daily_rate_cents,currency,gross_profit_cents,net_profit_cents,\
+        government_tax_cents,social_contribution_tax_cents,total_tax_cents\n"
+    );
+
+    for index in 0..count {
+        let days = 1 + index % 5;
+        file.push_str(&format!("i{},{},{},50000,EUR,0,0,0,0,0\n", index, start + index as u128 * step, days));
+    }
+
+    file
+}
+
+fn calc_taxes(c: &mut Criterion) {
+    let rules = Config::default().tax_rules(None).unwrap();
+    let tax_buckets = tax::tax_buckets(&rules.brackets, NaiveDate::from_ymd_opt(2026, 6, 1).unwrap());
+
+    let mut group = c.benchmark_group("calc_taxes");
+    // Incomes from a first small invoice up to one deep in the top bracket
+    for (gross_profit, prior_gross_profit) in [(50_000, 0), (1_000_000, 2_000_000), (5_000_000, 10_000_000)] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(prior_gross_profit + gross_profit),
+            &(gross_profit, prior_gross_profit),
+            |b, &(gross_profit, prior_gross_profit)| {
+                b.iter(|| tax::calc_taxes(black_box(gross_profit), prior_gross_profit, 0, &tax_buckets, &rules))
+            }
+        );
+    }
+    group.finish();
+}
+
+fn recalculate_year(c: &mut Criterion) {
+    let rules = Config::default().tax_rules(None).unwrap();
+
+    let mut group = c.benchmark_group("recalculate_year");
+    for count in [1_000, 10_000] {
+        let (invoices, _) = storage::read_invoices(year_file(count).as_bytes()).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &invoices, |b, invoices| {
+            b.iter(|| {
+                let mut invoices = invoices.clone();
+                tax::recalculate_year(&mut invoices, &[], &rules);
+                invoices
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, calc_taxes, recalculate_year);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use accountant_tool::Invoice;
+
+// Malformed files must come back as errors, never as panics
+fuzz_target!(|data: &[u8]| {
+    let _ = Invoice::read_invoices(data);
+});
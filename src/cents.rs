@@ -0,0 +1,79 @@
+/// Monetary amount in minor units, e.g. `1050` for 10.50 EUR.
+///
+/// Amounts are kept as whole cents so sums never drift, and converted from and to decimal
+/// amounts only where they are read from or shown to the user.
+pub type Cents = i64;
+
+const PER_UNIT: f64 = 100.0;
+
+/// Rates are applied in millionths, enough for any percentage with four decimals
+const RATE_SCALE: i128 = 1_000_000;
+
+/// Cents of a decimal amount, rounded to the nearest cent
+pub fn from_amount(amount: f64) -> Cents {
+    (amount * PER_UNIT).round() as Cents
+}
+
+/// Decimal amount of `cents`, for display and reports
+pub fn to_amount(cents: Cents) -> f64 {
+    cents as f64 / PER_UNIT
+}
+
+/// `cents` multiplied by `rate`, rounded half away from zero to the nearest cent
+pub fn apply_rate(cents: Cents, rate: f64) -> Cents {
+    let rate = (rate * RATE_SCALE as f64).round() as i128;
+    let product = cents as i128 * rate;
+    let half = RATE_SCALE / 2 * product.signum();
+
+    ((product + half) / RATE_SCALE) as Cents
+}
+
+/// Serializes cents as a decimal amount, so reports read the same as before amounts were cents
+pub fn serialize_amount<S: serde::Serializer>(cents: &Cents, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(to_amount(*cents))
+}
+
+/// Serializes cents as a decimal amount and `None` as null
+pub fn serialize_optional_amount<S: serde::Serializer>(
+    cents: &Option<Cents>,
+    serializer: S
+) -> Result<S::Ok, S::Error> {
+    match cents {
+        Some(cents) => serialize_amount(cents, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_round_to_the_nearest_cent() {
+        assert_eq!(from_amount(0.1 + 0.2), 30);
+        assert_eq!(from_amount(13_870.0), 1_387_000);
+        assert_eq!(from_amount(-12.345_6), -1235);
+        assert_eq!(to_amount(1050), 10.5);
+    }
+
+    #[test]
+    fn sums_of_cents_do_not_drift() {
+        let total: Cents = (0..10).map(|_| from_amount(0.1)).sum();
+        assert_eq!(total, 100);
+    }
+
+    #[test]
+    fn rates_round_half_away_from_zero() {
+        assert_eq!(apply_rate(2, 0.25), 1);
+        assert_eq!(apply_rate(1, 0.25), 0);
+        assert_eq!(apply_rate(1, 0.5), 1);
+        assert_eq!(apply_rate(-1, 0.5), -1);
+        assert_eq!(apply_rate(-3, 0.25), -1);
+    }
+
+    #[test]
+    fn rates_keep_four_decimals_of_a_percentage() {
+        assert_eq!(apply_rate(1_000_000, 0.206_3), 206_300);
+        assert_eq!(apply_rate(100_000_000, 0.000_001), 100);
+    }
+}
@@ -0,0 +1,1043 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::cents::{self, Cents};
+use crate::error::InvoiceError;
+use crate::format::{CurrencyStyle, MoneyFormat};
+use crate::hook::OnWrite;
+#[cfg(feature = "live-rates")]
+use crate::live_rates;
+use crate::rates::{RateProvider, StaticRates};
+#[cfg(feature = "sqlite")]
+use crate::sqlite_store::SqliteStore;
+use crate::storage::CsvStore;
+use crate::store::InvoiceStore;
+use crate::{note, with_path, write_currencies, Invoice, Notes, TaxBucket};
+
+/// Settings read from the config file, anything left out falls back to the built-in defaults.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Locale used to format amounts, e.g. `en` or `de`
+    pub locale: Option<String>,
+    /// Number of decimals shown for amounts
+    pub precision: Option<usize>,
+    /// Whether and where the currency is shown next to amounts
+    pub currency_style: Option<CurrencyStyle>,
+    /// Daily rate of a new invoice when none is given
+    pub daily_rate: Option<f64>,
+    /// Currency of a new invoice when none is given
+    pub currency: Option<String>,
+    /// VAT rate charged on a new invoice when none is given, e.g. `0.21`, none when left out
+    pub vat_rate: Option<f64>,
+    /// Currency codes accepted besides the ISO 4217 ones, e.g. for a local or made-up currency
+    pub custom_currencies: Vec<String>,
+    /// CSV file of `currency,rate` rows under that header replacing the built-in exchange rates
+    pub rates_file: Option<PathBuf>,
+    /// EUR value of one unit of a currency, on top of the built-in or file rates
+    pub rates: HashMap<String, f64>,
+    /// Fetch exchange rates over HTTP, requires the `live-rates` feature
+    pub live_rates: Option<LiveRatesConfig>,
+    /// Accept invoices at a daily rate of 0, e.g. pro-bono work counted in the days but not the revenue
+    pub allow_zero_rate: bool,
+    /// Smallest billable part of a day, e.g. `0.25` for quarter days, any amount when left out
+    pub billing_increment: Option<f64>,
+    /// Days without an invoice after which `gaps` reports the stretch
+    pub gap_days: Option<i64>,
+    /// Hours in a worked day, `info` shows the days as hours with it, 8 when left out
+    pub hours_per_day: Option<f64>,
+    /// Days ahead of today `new --date` accepts, 7 when left out
+    pub future_days: Option<i64>,
+    /// Field separator of CSV files read by `import` and written by `export`, `,` when left out
+    pub csv_delimiter: Option<char>,
+    /// Read and write decimals in those files with a comma, as spreadsheets in many locales do
+    pub decimal_comma: bool,
+    /// Character grouping thousands in numbers read by `import`, e.g. `" "` for `1 234,56`
+    pub thousands_separator: Option<char>,
+    /// Directory relative `--out` files are written to, the current directory when left out
+    pub export_dir: Option<PathBuf>,
+    /// Directory the yearly invoice and expense files are kept in, `default_invoice_dir` when left out
+    pub invoice_dir: Option<PathBuf>,
+    /// SQLite database the invoices are kept in instead of the yearly CSV files, requires the `sqlite` feature
+    pub database: Option<PathBuf>,
+    /// Shell command run after each write of an invoice file, with its path as `$1`, e.g. to commit it
+    pub on_write: Option<String>,
+    /// Start exports with `#` comment lines naming the issuer, time, version and tax profile
+    pub export_banner: bool,
+    /// When an invoice is due, 30 days after it was sent when left out
+    pub payment_terms: PaymentTerms,
+    /// Days on which no business day is counted for payment terms, besides weekends
+    pub holidays: Vec<NaiveDate>,
+    /// Brackets replacing the built-in ones from a date on, e.g. when the rules change mid-year
+    pub brackets: Vec<BracketPeriod>,
+    /// What the social contribution is computed on
+    pub social_base: SocialBase,
+    /// Share of the base paid as social contribution, 20.5% when left out
+    pub social_contribution_fee: Option<f64>,
+    /// Social contribution owed for a year however little was earned, in EUR
+    pub minimum_social_contribution: Option<f64>,
+    /// When the tax of each quarter is to be prepaid, checked by `prepayment-check`
+    pub prepayments: Prepayments,
+    /// Tax regimes of other legal structures, selected with `--profile <name>`
+    pub profiles: BTreeMap<String, Profile>,
+    /// Who sends the invoices, shown on the rendered invoice
+    pub issuer: Issuer,
+    /// HTML file `render --format html` fills in instead of the built-in layout
+    pub invoice_template: Option<PathBuf>,
+    /// Clients invoiced repeatedly, `new --client` takes their key and fills in their details
+    pub clients: BTreeMap<String, Client>,
+    /// Invoices generated every month by `recurring run`
+    pub recurring: Vec<Recurring>
+}
+
+/// Amount the social contribution fee is applied to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SocialBase {
+    /// The gross profit
+    Gross,
+    /// What remains of the gross profit after government tax
+    #[default]
+    AfterIncomeTax,
+    /// A notional professional income, this share of the gross profit
+    ShareOfGross(f64)
+}
+
+/// Tax regime of a legal structure next to the default one, e.g. a company.
+///
+/// Settings left out fall back to the built-in ones, not to those of the default regime. A tax-free
+/// allowance is a first bucket with `perc = 0`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub brackets: Vec<BracketPeriod>,
+    pub social_base: SocialBase,
+    pub social_contribution_fee: Option<f64>,
+    pub minimum_social_contribution: Option<f64>
+}
+
+const SOCIAL_CONTRIBUTION_FEE: f64 = 0.205;
+
+/// Everything the taxes of an invoice are computed with.
+#[derive(Debug, Clone)]
+pub struct TaxRules {
+    /// Profile the rules belong to, empty for the default regime
+    pub profile: String,
+    pub brackets: Vec<BracketPeriod>,
+    pub social_base: SocialBase,
+    pub social_contribution_fee: f64,
+    /// Yearly social contribution topped up to by `recalculate`, 0 for none
+    pub minimum_social_contribution: Cents
+}
+
+impl Config {
+    /// Rules of the profile called `profile`, or of the default regime when `None`
+    pub fn tax_rules(&self, profile: Option<&str>) -> Result<TaxRules, InvoiceError> {
+        let Some(name) = profile else {
+            return Ok(TaxRules {
+                profile: String::new(),
+                brackets: self.brackets.clone(),
+                social_base: self.social_base,
+                social_contribution_fee: self.social_contribution_fee.unwrap_or(SOCIAL_CONTRIBUTION_FEE),
+                minimum_social_contribution: self.minimum_social_contribution.map_or(0, cents::from_amount)
+            });
+        };
+
+        let Some(found) = self.profiles.get(name) else {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(InvoiceError::Validation(format!(
+                "no profile `{}` in the config, known profiles: {}",
+                name,
+                if known.is_empty() { "none".to_string() } else { known.join(", ") }
+            )));
+        };
+
+        Ok(TaxRules {
+            profile: name.to_string(),
+            brackets: found.brackets.clone(),
+            social_base: found.social_base,
+            social_contribution_fee: found.social_contribution_fee.unwrap_or(SOCIAL_CONTRIBUTION_FEE),
+            minimum_social_contribution: found.minimum_social_contribution.map_or(0, cents::from_amount)
+        })
+    }
+
+    /// Client of the registry with the key or the name `client`
+    pub fn client(&self, client: &str) -> Option<&Client> {
+        self.clients.get(client).or_else(|| {
+            self.clients.values().find(|registered| registered.name.as_deref() == Some(client))
+        })
+    }
+
+    /// How amounts are shown, with the `flags` over the settings of the file
+    pub fn money_format(&self, flags: Flags) -> Result<MoneyFormat, InvoiceError> {
+        let mut money = match flags.locale.or(self.locale.as_deref()) {
+            Some(locale) => MoneyFormat::from_locale(locale)
+                .map_err(|err| InvoiceError::Validation(format!("unknown locale `{}`: {}", locale, err)))?,
+            None => MoneyFormat::neutral(),
+        };
+        if let Some(precision) = flags.precision.or(self.precision) {
+            money = money.with_precision(precision);
+        }
+        if let Some(currency_style) = flags.currency_style.or(self.currency_style) {
+            money = money.with_currency_style(currency_style);
+        }
+
+        Ok(money)
+    }
+
+    /// Rates of the `rates_file` and `rates`, fetched live on top of them with `live_rates`. Why the live
+    /// ones can't be used goes to `notes`.
+    pub fn rate_provider(&self, notes: &Notes) -> Result<Box<dyn RateProvider>, InvoiceError> {
+        let static_rates = match &self.rates_file {
+            Some(path) => {
+                StaticRates::from_csv(File::open(path).map_err(|err| with_path(err, &path.display().to_string()))?)?
+            }
+            None => StaticRates::default(),
+        }.with_rates(&self.rates)?;
+
+        Ok(live_rate_provider(self, static_rates, notes))
+    }
+
+    /// The yearly CSV files of `invoice_dir`, in the platform data directory by default
+    pub fn csv_store(&self, on_write: OnWrite) -> Result<CsvStore, InvoiceError> {
+        let dir = self.invoice_dir.clone().or_else(default_invoice_dir).ok_or_else(|| {
+            InvoiceError::Validation("no data directory found, set `invoice_dir` in the config".to_string())
+        })?;
+
+        Ok(CsvStore::new(dir, on_write))
+    }
+
+    /// The `database` when one is configured, the CSV files otherwise
+    pub fn invoice_store(&self, on_write: OnWrite, notes: &Notes) -> Result<Box<dyn InvoiceStore>, InvoiceError> {
+        let Some(path) = &self.database else {
+            let store = self.csv_store(on_write)?;
+            if self.invoice_dir.is_none() {
+                warn_about_old_invoice_dir(&store, notes);
+            }
+            return Ok(Box::new(store));
+        };
+
+        database_store(path, on_write)
+    }
+}
+
+/// Older versions kept the invoice files in `src` of the working directory, which is no longer read
+fn warn_about_old_invoice_dir(store: &dyn InvoiceStore, notes: &Notes) {
+    let old_store = CsvStore::new(PathBuf::from("src"), OnWrite::default());
+    let has_invoices = |store: &dyn InvoiceStore| store.years().is_ok_and(|years| !years.is_empty());
+    if has_invoices(&old_store) && !has_invoices(store) {
+        note(notes, &format!(
+            "Warning: found invoice files in {}, move them to {} or set `invoice_dir = \"src\"` in the config",
+            old_store.location(),
+            store.location()
+        ));
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn database_store(path: &Path, on_write: OnWrite) -> Result<Box<dyn InvoiceStore>, InvoiceError> {
+    Ok(Box::new(SqliteStore::open(path, on_write)?))
+}
+
+// Unlike live rates there is nothing to fall back on, the CSV files hold other invoices than the database
+#[cfg(not(feature = "sqlite"))]
+fn database_store(_path: &Path, _on_write: OnWrite) -> Result<Box<dyn InvoiceStore>, InvoiceError> {
+    Err(InvoiceError::Validation(
+        "`database` is configured but this build lacks the `sqlite` feature, build with `--features sqlite`"
+            .to_string()
+    ))
+}
+
+#[cfg(feature = "live-rates")]
+fn live_rate_provider(config: &Config, static_rates: StaticRates, notes: &Notes) -> Box<dyn RateProvider> {
+    const TTL_HOURS: u64 = 24;
+
+    let Some(live) = &config.live_rates else {
+        return Box::new(static_rates);
+    };
+    let Some(cache) = live.cache.clone().or_else(live_rates::RateCache::default_path) else {
+        note(notes, "No cache directory found for exchange rates, set `live_rates.cache`");
+        return Box::new(static_rates);
+    };
+
+    Box::new(live_rates::LiveRates::load(
+        live.endpoint.as_deref().unwrap_or(live_rates::LiveRates::ENDPOINT),
+        &cache,
+        std::time::Duration::from_secs(live.ttl_hours.unwrap_or(TTL_HOURS) * 60 * 60),
+        static_rates,
+        notes
+    ))
+}
+
+#[cfg(not(feature = "live-rates"))]
+fn live_rate_provider(config: &Config, static_rates: StaticRates, notes: &Notes) -> Box<dyn RateProvider> {
+    if config.live_rates.is_some() {
+        note(notes, "`live_rates` is configured but this build lacks the `live-rates` feature, using static rates");
+    }
+
+    Box::new(static_rates)
+}
+
+impl TaxRules {
+    /// Bracket periods that apply to `year`, with the built-in brackets where none are configured
+    pub fn year_bracket_periods(&self, year: i32) -> Vec<BracketPeriod> {
+        crate::tax::year_bracket_periods(&self.brackets, year)
+    }
+}
+
+/// Tax brackets in effect from `from` until the next period starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BracketPeriod {
+    pub from: NaiveDate,
+    /// Ordered by `to`, the last one without `to` so it covers any income above
+    pub buckets: Vec<TaxBucket>
+}
+
+impl BracketPeriod {
+    fn validate(&self, prefix: &str) -> Result<(), InvoiceError> {
+        let invalid = |reason: &str| {
+            InvoiceError::Validation(format!("{}brackets from {} {}", prefix, self.from, reason))
+        };
+
+        let Some((last, rest)) = self.buckets.split_last() else {
+            return Err(invalid("have no buckets"));
+        };
+        if last.to.is_some() || rest.iter().any(|tax_bucket| tax_bucket.to.is_none()) {
+            return Err(invalid("need exactly one bucket without `to`, the last one"));
+        }
+        if rest.windows(2).any(|pair| pair[0].to >= pair[1].to) {
+            return Err(invalid("need to be ordered by `to`"));
+        }
+        // A floor below where the previous bucket ends would tax the income in between twice, one above
+        // it leave that income untaxed. A rate that only applies above a floor has a 0% bucket below it.
+        let mut previous_to = 0;
+        for tax_bucket in &self.buckets {
+            if let Some(from) = tax_bucket.from {
+                if from < previous_to {
+                    return Err(invalid(&format!("overlap, a bucket from {} starts before {}", from, previous_to)));
+                } else if from > previous_to {
+                    return Err(invalid(&format!(
+                        "leave a gap, a bucket from {} starts after {}, fill it with a 0% bucket",
+                        from, previous_to
+                    )));
+                } else if tax_bucket.to.is_some_and(|to| to <= from) {
+                    let reason = format!("need every bucket to end after it starts, unlike the one from {}", from);
+                    return Err(invalid(&reason));
+                }
+            }
+            previous_to = tax_bucket.to.unwrap_or(previous_to);
+        }
+        if self.buckets.iter().any(|tax_bucket| !(0.0..=1.0).contains(&tax_bucket.perc)) {
+            return Err(invalid("need every `perc` between 0 and 1"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Days a client has to pay an invoice, e.g. "net 30 business days".
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaymentTerms {
+    pub days: u32,
+    /// Count only weekdays that aren't in `holidays`
+    pub business_days: bool
+}
+
+impl Default for PaymentTerms {
+    fn default() -> Self {
+        PaymentTerms { days: 30, business_days: false }
+    }
+}
+
+impl PaymentTerms {
+    /// Day an invoice sent on `sent` is due. With business days the due date itself is a business
+    /// day too, since counting ends on the last one.
+    pub fn due_date(&self, sent: NaiveDate, holidays: &[NaiveDate]) -> NaiveDate {
+        if !self.business_days {
+            return sent + Duration::days(self.days as i64);
+        }
+
+        let mut due = sent;
+        let mut counted = 0;
+        while counted < self.days {
+            due += Duration::days(1);
+            let weekend = matches!(due.weekday(), Weekday::Sat | Weekday::Sun);
+            if !weekend && !holidays.contains(&due) {
+                counted += 1;
+            }
+        }
+
+        due
+    }
+}
+
+/// Due dates of the quarterly tax prepayments and the interest charged on what was paid short.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Prepayments {
+    /// Day each quarter's prepayment is due, as `MM-DD`, in the order of the quarters
+    pub due: Vec<String>,
+    /// Yearly interest charged on the tax not prepaid by a due date
+    pub penalty_rate: f64,
+    /// Prepayments made, one amount per quarter under the year, e.g. `2026 = [3000.0, 2500.0]`
+    pub paid: BTreeMap<String, Vec<f64>>
+}
+
+impl Default for Prepayments {
+    fn default() -> Self {
+        Prepayments {
+            due: ["04-10", "07-10", "10-10", "12-20"].map(String::from).to_vec(),
+            penalty_rate: 0.0675,
+            paid: BTreeMap::new()
+        }
+    }
+}
+
+impl Prepayments {
+    /// Due date of each quarter's prepayment in `year`
+    pub fn due_dates(&self, year: i32) -> Result<Vec<NaiveDate>, InvoiceError> {
+        self.due
+            .iter()
+            .map(|day| {
+                NaiveDate::parse_from_str(&format!("{}-{}", year, day), "%Y-%m-%d").map_err(|_| {
+                    InvoiceError::Validation(format!("invalid prepayment due day `{}`, expected MM-DD", day))
+                })
+            })
+            .collect()
+    }
+
+    /// Prepayments of `year` from the config, none for quarters left out
+    pub fn paid(&self, year: i32) -> Vec<f64> {
+        self.paid.get(&year.to_string()).cloned().unwrap_or_default()
+    }
+
+    fn validate(&self) -> Result<(), InvoiceError> {
+        // A leap year accepts every day of the year, 02-29 included
+        let due = self.due_dates(2028)?;
+        if due.len() != 4 || due.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(InvoiceError::invalid("prepayments.due", "needs four days in order"));
+        }
+        if self.penalty_rate < 0.0 || !self.penalty_rate.is_finite() {
+            return Err(InvoiceError::invalid("prepayments.penalty_rate", "can not be negative"));
+        }
+        for (year, paid) in &self.paid {
+            if year.parse::<i32>().is_err() {
+                return Err(InvoiceError::Validation(format!("`prepayments.paid` has `{}` for a year", year)));
+            }
+            if paid.len() > 4 || paid.iter().any(|amount| *amount < 0.0 || !amount.is_finite()) {
+                let message = format!("`prepayments.paid.{}` needs at most four amounts, none negative", year);
+                return Err(InvoiceError::Validation(message));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Issuer {
+    pub name: Option<String>,
+    /// Postal address, one line per line of the string
+    pub address: Option<String>,
+    pub vat_id: Option<String>,
+    /// Account the client pays to, shown with the due date
+    pub iban: Option<String>
+}
+
+/// Details of a client of the registry, shown on the invoices rendered for them.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Client {
+    /// Name recorded on the invoice, the key of the registry when left out
+    pub name: Option<String>,
+    /// Postal address, one line per line of the string
+    pub address: Option<String>,
+    pub vat_id: Option<String>
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiveRatesConfig {
+    /// URL answering with `{"rates": {"USD": 1.08}}`, the amount of each currency one EUR buys
+    pub endpoint: Option<String>,
+    /// Hours fetched rates are reused before fetching them again
+    pub ttl_hours: Option<u64>,
+    /// Where fetched rates are cached, defaults to the platform cache directory
+    pub cache: Option<PathBuf>
+}
+
+/// Template of an invoice that comes back every month, e.g. a retainer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recurring {
+    /// Generated invoices are named `<name_prefix>_<year>_<month>`
+    pub name_prefix: String,
+    pub days_worked: f64,
+    pub daily_rate: Option<f64>,
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub client: Option<String>,
+    #[serde(default)]
+    pub client_vat_id: Option<String>,
+    /// The client accounts for the VAT, see `new --reverse-charge`
+    #[serde(default)]
+    pub reverse_charge: bool,
+    /// Day of the month from which the invoice of that month is generated
+    pub day_of_month: u32,
+    #[serde(default = "active_default")]
+    pub active: bool
+}
+
+fn active_default() -> bool {
+    true
+}
+
+impl Recurring {
+    pub fn invoice_name(&self, year: i32, month: u32) -> String {
+        format!("{}_{}_{:02}", self.name_prefix, year, month)
+    }
+
+    /// Day of `month` the invoice is due from, the last day of a month shorter than `day_of_month`
+    pub fn due_day(&self, year: i32, month: u32) -> u32 {
+        let next_month = if month == 12 {
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(year, month + 1, 1)
+        };
+        let last_day = next_month.and_then(|next_month| next_month.pred_opt()).map_or(28, |last| last.day());
+
+        self.day_of_month.min(last_day)
+    }
+
+    fn validate(&self) -> Result<(), InvoiceError> {
+        if !(1..=31).contains(&self.day_of_month) {
+            return Err(InvoiceError::Validation(format!(
+                "`day_of_month` of recurring invoice `{}` needs to be between 1 and 31", self.name_prefix
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+pub const TEMPLATE: &str = r#"# Configuration of accountant_tool, uncomment a setting to change it.
+
+# Locale used to format amounts, e.g. "en" or "de"
+# locale = "en"
+
+# Number of decimals shown for amounts
+# precision = 2
+
+# Currency next to amounts: "none", "symbol", "symbol-after", "code" or "code-after"
+# currency_style = "none"
+
+# Daily rate and currency of a new invoice when none is given
+# daily_rate = 500.0
+# currency = "EUR"
+
+# VAT charged on top of a new invoice when none is given, none when left out as under the
+# small business exemption. `new --reverse-charge` charges none to a client in another EU country
+# vat_rate = 0.21
+
+# Currency codes accepted besides the ISO 4217 ones, they need a rate in the rates_file
+# custom_currencies = ["XBT"]
+
+# Accept a daily rate of 0, for pro-bono work that counts in the days worked but not the revenue
+# allow_zero_rate = true
+
+# Smallest billable part of a day, e.g. 0.25 for quarter days
+# billing_increment = 0.25
+
+# Days without an invoice after which `gaps` reports the stretch
+# gap_days = 30
+
+# Hours in a worked day, `info` shows the hours worked and net per hour with it
+# hours_per_day = 8.0
+
+# Days ahead of today an invoice can be dated with `new --date`
+# future_days = 7
+
+# Field separator and decimal comma of the CSV files of `import` and `export`, e.g. for
+# spreadsheets in a locale that writes 1,5 for 1.5
+# csv_delimiter = ";"
+# decimal_comma = true
+
+# Character grouping thousands in the numbers `import` reads, e.g. " " for 1 234,56
+# thousands_separator = " "
+
+# Directory relative `--out` files of exports, reports and rendered invoices are written to
+# export_dir = "exports"
+
+# Directory the yearly invoice and expense files are kept in, the platform data directory, e.g.
+# ~/.local/share/accountant_tool, when left out. "src" keeps them where older versions did
+# invoice_dir = "/home/me/accounting"
+
+# Keep the invoices in an SQLite database instead of the yearly CSV files, which then works from
+# any directory. Requires building with `--features sqlite`, `migrate` copies the CSV files in
+# database = "/home/me/accounting/invoices.sqlite"
+
+# Shell command run after each write of an invoice file, which is passed as $1 and in
+# ACCOUNTANT_TOOL_FILE, e.g. to commit it to git. A failing command is reported, not fatal
+# on_write = 'git -C src add -A && git -C src commit -qm "Update $1"'
+
+# Start exports with comment lines naming the issuer, generation time, version and tax
+# profile, `import` skips them
+# export_banner = true
+
+# Days on which no business day is counted for payment terms, besides weekends
+# holidays = ["2026-12-25", "2027-01-01"]
+
+# What the social contribution is computed on: "gross", "after-income-tax" or a notional
+# income as a share of the gross profit, e.g. { share-of-gross = 0.7 }
+# social_base = "after-income-tax"
+
+# Share of that base paid as social contribution
+# social_contribution_fee = 0.205
+
+# Social contribution owed for a year however little was earned, `recalculate` tops the
+# invoices of the year up to it
+# minimum_social_contribution = 3500.0
+
+# CSV file of `currency,rate` rows under that header replacing the built-in exchange rates to EUR
+# rates_file = "rates.csv"
+
+# Tables go below all plain settings, TOML assigns anything after a table header to it.
+
+# EUR value of one unit of a currency, on top of the built-in or file rates
+# [rates]
+# USD = 0.92
+
+# Fetch exchange rates over HTTP, requires building with `--features live-rates`
+# [live_rates]
+# endpoint = "https://api.frankfurter.app/latest?from=EUR"
+# ttl_hours = 24
+
+# When invoices are due, used by `overdue`, e.g. net 30 business days
+# [payment_terms]
+# days = 30
+# business_days = true
+
+# Due days of the quarterly tax prepayments, the yearly interest charged on what wasn't
+# prepaid by one and the prepayments made, one amount per quarter under the year
+# [prepayments]
+# due = ["04-10", "07-10", "10-10", "12-20"]
+# penalty_rate = 0.0675
+# [prepayments.paid]
+# 2026 = [3000.0, 2500.0]
+
+# Brackets replacing the built-in ones from a date on, one block per change of the rules. A
+# bucket can state its `from`, which needs to be where the one before it ends, untaxed income
+# takes a bucket with `perc = 0.0`. The first block holds the built-in brackets, a starting
+# point for next year's
+# [[brackets]]
+# from = "2026-01-01"
+# buckets = [
+#     { from = 0, to = 13870, perc = 0.25 },
+#     { to = 24480, perc = 0.40 },
+#     { to = 42370, perc = 0.45 },
+#     { perc = 0.50 },
+# ]
+# [[brackets]]
+# from = "2027-01-01"
+# buckets = [
+#     { to = 15000, perc = 0.25 },
+#     { to = 26000, perc = 0.40 },
+#     { to = 45000, perc = 0.45 },
+#     { perc = 0.50 },
+# ]
+
+# Tax regime of another legal structure, used with `--profile company`, one table per profile
+# [profiles.company]
+# social_base = "gross"
+# social_contribution_fee = 0.0
+# [[profiles.company.brackets]]
+# from = "2026-01-01"
+# buckets = [
+#     { to = 100000, perc = 0.20 },
+#     { perc = 0.25 },
+# ]
+
+# Your details, shown on the invoices rendered with `render`
+# [issuer]
+# name = "Jane Doe Consulting"
+# address = """
+# Main Street 1
+# 1000 Brussels"""
+# vat_id = "BE0123456789"
+# iban = "BE68 5390 0754 7034"
+
+# HTML layout of `render --format html`, filled in at {{invoice_number}}, {{date}}, {{due}},
+# {{issuer}}, {{client}}, {{lines}}, {{totals}} and {{payment}}
+# invoice_template = "/home/me/accounting/invoice.html"
+
+# Clients invoiced repeatedly, `new --client acme` records the name and VAT number of the
+# client and `render` shows its address
+# [clients.acme]
+# name = "Acme NV"
+# address = """
+# Station Road 5
+# 2000 Antwerp"""
+# vat_id = "BE0987654321"
+"#;
+
+/// Config file in the platform config directory, e.g. `~/.config/accountant_tool/config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("accountant_tool").join("config.toml"))
+}
+
+/// Directory the invoice files are kept in unless the config sets `invoice_dir`, e.g.
+/// `~/.local/share/accountant_tool`
+pub fn default_invoice_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("accountant_tool"))
+}
+
+/// Reads the config at `path`, a missing file gives the built-in defaults.
+pub fn load(path: Option<&Path>) -> Result<Config, InvoiceError> {
+    let Some(path) = path.filter(|path| path.exists()) else {
+        return Ok(Config::default());
+    };
+
+    let contents = fs::read_to_string(path)?;
+    parse(&contents, path)
+}
+
+/// Reads and checks the config `contents` of the file at `path`
+fn parse(contents: &str, path: &Path) -> Result<Config, InvoiceError> {
+    let config: Config = toml::from_str(contents)
+        .map_err(|err| InvoiceError::Parse(format!("invalid config `{}`: {}", path.display(), err)))?;
+
+    validate_regime(
+        &config.brackets,
+        config.social_base,
+        config.social_contribution_fee,
+        config.minimum_social_contribution,
+        ""
+    )?;
+    for (name, profile) in &config.profiles {
+        validate_regime(
+            &profile.brackets,
+            profile.social_base,
+            profile.social_contribution_fee,
+            profile.minimum_social_contribution,
+            &format!("profiles.{}.", name)
+        )?;
+    }
+    config.prepayments.validate()?;
+    for recurring in &config.recurring {
+        recurring.validate()?;
+    }
+    if config.hours_per_day.is_some_and(|hours| hours <= 0.0 || hours > 24.0) {
+        return Err(InvoiceError::invalid("hours_per_day", "needs to be more than 0 and at most 24"));
+    }
+    // Days are rounded to a multiple of it, so it has to be a part of a day that can be divided by
+    if config.billing_increment.is_some_and(|increment| increment <= 0.0 || !increment.is_finite()) {
+        return Err(InvoiceError::invalid("billing_increment", "needs to be more than 0"));
+    }
+
+    Ok(config)
+}
+
+/// Checks the tax settings of the default regime or of a profile, `prefix` names the table they are in
+fn validate_regime(
+    brackets: &[BracketPeriod],
+    social_base: SocialBase,
+    social_contribution_fee: Option<f64>,
+    minimum_social_contribution: Option<f64>,
+    prefix: &str
+) -> Result<(), InvoiceError> {
+    for period in brackets {
+        period.validate(prefix)?;
+    }
+    if let SocialBase::ShareOfGross(share) = social_base {
+        if !(0.0..=1.0).contains(&share) {
+            let message = format!("`{}social_base.share-of-gross` needs to be between 0 and 1", prefix);
+            return Err(InvoiceError::Validation(message));
+        }
+    }
+    if social_contribution_fee.is_some_and(|fee| !(0.0..=1.0).contains(&fee)) {
+        let message = format!("`{}social_contribution_fee` needs to be between 0 and 1", prefix);
+        return Err(InvoiceError::Validation(message));
+    }
+    if minimum_social_contribution.is_some_and(|minimum| minimum < 0.0 || !minimum.is_finite()) {
+        let message = format!("`{}minimum_social_contribution` can not be negative", prefix);
+        return Err(InvoiceError::Validation(message));
+    }
+
+    Ok(())
+}
+
+/// Appends a recurring invoice to the config at `path`, keeping the rest of the file as it is.
+pub fn add_recurring(path: &Path, recurring: Recurring) -> Result<(), InvoiceError> {
+    #[derive(Serialize)]
+    struct Entry {
+        recurring: Vec<Recurring>
+    }
+
+    recurring.validate()?;
+    let config = load(Some(path))?;
+    if config.recurring.iter().any(|existing| existing.name_prefix == recurring.name_prefix) {
+        return Err(InvoiceError::Validation(format!("a recurring invoice `{}` already exists", recurring.name_prefix)));
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let entry = toml::to_string(&Entry { recurring: vec![recurring] })
+        .map_err(|err| InvoiceError::Parse(err.to_string()))?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    write!(file, "\n{}", entry)?;
+
+    Ok(())
+}
+
+/// Writes the commented template to `path`, refusing to overwrite an existing config.
+pub fn write_template(path: &Path) -> Result<(), InvoiceError> {
+    if path.exists() {
+        return Err(InvoiceError::Validation(format!("config `{}` already exists", path.display())));
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, TEMPLATE)?;
+
+    Ok(())
+}
+
+/// Settings given as flags, which take precedence over the config file
+#[derive(Clone, Copy)]
+pub struct Flags<'a> {
+    pub locale: Option<&'a str>,
+    pub precision: Option<usize>,
+    pub currency_style: Option<CurrencyStyle>,
+    pub out_dir: Option<&'a Path>
+}
+
+/// Writes every setting in effect after merging flags, the config file and the built-in defaults,
+/// followed by where each value came from. `config_source` says how `config_path` was picked.
+#[allow(clippy::too_many_arguments)]
+pub fn write_effective<W: Write>(
+    mut writer: W,
+    config_path: Option<&Path>,
+    config_source: &str,
+    flags: Flags,
+    config: &Config,
+    rules: &TaxRules,
+    rates: &dyn RateProvider,
+    year: i32
+) -> Result<(), InvoiceError> {
+    let Flags { locale, precision, currency_style, out_dir } = flags;
+    fn setting<T: Display>(
+        writer: &mut dyn Write,
+        key: &str,
+        flag: Option<T>,
+        flag_name: &str,
+        file: Option<T>,
+        default: &str
+    ) -> io::Result<()> {
+        match (flag, file) {
+            (Some(value), _) => writeln!(writer, "{} = {} ({})", key, value, flag_name),
+            (None, Some(value)) => writeln!(writer, "{} = {} (config file)", key, value),
+            (None, None) => writeln!(writer, "{} = {} (default)", key, default),
+        }
+    }
+    let style_name = |style: CurrencyStyle| {
+        style.to_possible_value().map_or(String::new(), |value| value.get_name().to_string())
+    };
+
+    match config_path {
+        Some(path) if path.exists() => writeln!(writer, "Config file: {} ({})", path.display(), config_source)?,
+        Some(path) => writeln!(writer, "Config file: {} ({}, not created yet)", path.display(), config_source)?,
+        None => writeln!(writer, "Config file: none, no config directory found")?,
+    }
+    setting(
+        &mut writer,
+        "export_dir",
+        out_dir.map(Path::display),
+        "--out-dir",
+        config.export_dir.as_deref().map(Path::display),
+        "current directory"
+    )?;
+
+    setting(&mut writer, "locale", locale, "--locale", config.locale.as_deref(), "none")?;
+    setting(&mut writer, "precision", precision, "--precision", config.precision, &MoneyFormat::PRECISION.to_string())?;
+    setting(
+        &mut writer,
+        "currency_style",
+        currency_style.map(style_name),
+        "--currency-style",
+        config.currency_style.map(style_name),
+        &style_name(CurrencyStyle::default())
+    )?;
+    setting(&mut writer, "daily_rate", None, "", config.daily_rate, &Invoice::DAILY_RATE.to_string())?;
+    setting(&mut writer, "currency", None, "", config.currency.as_deref(), Invoice::CURRENCY)?;
+    setting(&mut writer, "vat_rate", None, "", config.vat_rate, &Invoice::VAT_RATE.to_string())?;
+    setting(&mut writer, "allow_zero_rate", None, "", config.allow_zero_rate.then_some(true), "false")?;
+    setting(&mut writer, "billing_increment", None, "", config.billing_increment, "any amount")?;
+    setting(&mut writer, "gap_days", None, "", config.gap_days, &Invoice::GAP_DAYS.to_string())?;
+    setting(&mut writer, "hours_per_day", None, "", config.hours_per_day, &Invoice::HOURS_PER_DAY.to_string())?;
+    setting(&mut writer, "future_days", None, "", config.future_days, &Invoice::FUTURE_DAYS.to_string())?;
+    setting(&mut writer, "on_write", None, "", config.on_write.as_deref(), "none")?;
+    let default_dir = default_invoice_dir()
+        .map_or("none, no data directory found".to_string(), |dir| dir.display().to_string());
+    setting(&mut writer, "invoice_dir", None, "", config.invoice_dir.as_deref().map(Path::display), &default_dir)?;
+    let database = config.database.as_deref().map(Path::display);
+    setting(&mut writer, "database", None, "", database, "none, the yearly CSV files")?;
+    let terms = &config.payment_terms;
+    writeln!(
+        writer,
+        "payment_terms = {} {}days ({})",
+        terms.days,
+        if terms.business_days { "business " } else { "" },
+        if *terms == PaymentTerms::default() { "default" } else { "config file" }
+    )?;
+    match config.custom_currencies.len() {
+        0 => writeln!(writer, "custom_currencies = none (default)")?,
+        _ => writeln!(writer, "custom_currencies = {} (config file)", config.custom_currencies.join(", "))?,
+    }
+    match config.holidays.len() {
+        0 => writeln!(writer, "holidays = none (default)")?,
+        count => writeln!(writer, "holidays = {} dates (config file)", count)?,
+    }
+
+    // Tax settings come from the profile when one is selected, from the top level of the file otherwise
+    let (source, social_base, fee, minimum) = match config.profiles.get(&rules.profile) {
+        Some(profile) => (
+            format!("profile {}", rules.profile),
+            profile.social_base,
+            profile.social_contribution_fee,
+            profile.minimum_social_contribution
+        ),
+        None => (
+            "config file".to_string(),
+            config.social_base,
+            config.social_contribution_fee,
+            config.minimum_social_contribution
+        ),
+    };
+    let tax_source = |configured: bool| if configured { source.as_str() } else { "default" };
+
+    if !rules.profile.is_empty() {
+        writeln!(writer, "profile = {} (--profile)", rules.profile)?;
+    }
+    let social_base_name = match rules.social_base {
+        SocialBase::Gross => "gross".to_string(),
+        SocialBase::AfterIncomeTax => "after-income-tax".to_string(),
+        SocialBase::ShareOfGross(share) => format!("{{ share-of-gross = {} }}", share),
+    };
+    writeln!(writer, "social_base = {} ({})", social_base_name, tax_source(social_base != SocialBase::default()))?;
+    writeln!(writer, "social_contribution_fee = {} ({})", rules.social_contribution_fee, tax_source(fee.is_some()))?;
+    match minimum {
+        Some(minimum) => writeln!(writer, "minimum_social_contribution = {} ({})", minimum, source)?,
+        None => writeln!(writer, "minimum_social_contribution = none (default)")?,
+    }
+
+    writeln!(writer, "Brackets for {} ({}):", year, tax_source(!rules.brackets.is_empty()))?;
+    for period in rules.year_bracket_periods(year) {
+        writeln!(writer, "  From {}:", period.from)?;
+        for tax_bucket in &period.buckets {
+            let perc = tax_bucket.perc * 100.0;
+            match (tax_bucket.from, tax_bucket.to) {
+                (Some(from), Some(to)) => writeln!(writer, "    {}% from {} up to {}", perc, from, to)?,
+                (None, Some(to)) => writeln!(writer, "    {}% up to {}", perc, to)?,
+                (Some(from), None) => writeln!(writer, "    {}% above {}", perc, from)?,
+                (None, None) => writeln!(writer, "    {}% above", perc)?,
+            }
+        }
+    }
+
+    let rates_source = match (&config.rates_file, config.rates.is_empty()) {
+        (Some(path), _) => format!("{} and config file", path.display()),
+        (None, false) => "built-in and config file".to_string(),
+        (None, true) => "built-in".to_string(),
+    };
+    writeln!(writer, "Exchange rates ({}):", rates_source)?;
+    Ok(write_currencies(&mut writer, rates)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recurring(day_of_month: u32) -> Recurring {
+        Recurring {
+            name_prefix: "retainer".to_string(),
+            days_worked: 2.0,
+            daily_rate: None,
+            currency: None,
+            client: None,
+            client_vat_id: None,
+            reverse_charge: false,
+            day_of_month,
+            active: true
+        }
+    }
+
+    #[test]
+    fn billing_increment_needs_to_be_positive() {
+        let path = Path::new("config.toml");
+        assert!(parse("billing_increment = 0.0", path).is_err());
+        assert!(parse("billing_increment = -0.5", path).is_err());
+        assert_eq!(parse("billing_increment = 0.25", path).unwrap().billing_increment, Some(0.25));
+        assert_eq!(parse("", path).unwrap().billing_increment, None);
+    }
+
+    fn period(buckets: Vec<TaxBucket>) -> BracketPeriod {
+        BracketPeriod { from: NaiveDate::from_ymd_opt(2026, 1, 1).unwrap(), buckets }
+    }
+
+    #[test]
+    fn brackets_can_not_leave_a_gap_or_overlap() {
+        let gap = period(vec![
+            TaxBucket { from: None, to: Some(10_000), perc: 0.25 },
+            TaxBucket { from: Some(20_000), to: None, perc: 0.1 }
+        ]);
+        assert!(gap.validate("").is_err());
+
+        let untaxed = period(vec![
+            TaxBucket { from: None, to: Some(10_000), perc: 0.25 },
+            TaxBucket { from: None, to: Some(20_000), perc: 0.0 },
+            TaxBucket { from: Some(20_000), to: None, perc: 0.1 }
+        ]);
+        assert!(untaxed.validate("").is_ok());
+
+        let overlap = period(vec![
+            TaxBucket { from: None, to: Some(10_000), perc: 0.25 },
+            TaxBucket { from: Some(5_000), to: None, perc: 0.1 }
+        ]);
+        assert!(overlap.validate("").is_err());
+
+        let empty = period(vec![
+            TaxBucket { from: Some(0), to: Some(0), perc: 0.25 },
+            TaxBucket { from: None, to: None, perc: 0.1 }
+        ]);
+        assert!(empty.validate("").is_err());
+    }
+
+    #[test]
+    fn due_day_is_clamped_to_the_end_of_short_months() {
+        assert_eq!(recurring(31).due_day(2026, 2), 28);
+        assert_eq!(recurring(31).due_day(2028, 2), 29);
+        assert_eq!(recurring(31).due_day(2026, 4), 30);
+        assert_eq!(recurring(31).due_day(2026, 12), 31);
+        assert_eq!(recurring(15).due_day(2026, 2), 15);
+    }
+
+    #[test]
+    fn day_of_month_outside_the_month_is_rejected() {
+        assert!(recurring(0).validate().is_err());
+        assert!(recurring(32).validate().is_err());
+        assert!(recurring(1).validate().is_ok());
+        assert!(recurring(31).validate().is_ok());
+    }
+}
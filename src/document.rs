@@ -0,0 +1,231 @@
+use std::io::Write;
+
+use chrono::NaiveDate;
+
+use crate::cents::Cents;
+use crate::config::{Client, Issuer};
+use crate::error::InvoiceError;
+use crate::format::MoneyFormat;
+use crate::storage;
+use crate::Invoice;
+
+/// Built-in layout of `write_invoice_html`, the `invoice_template` of the config replaces it
+pub const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{title}} {{invoice_number}}</title>
+<style>
+body { font-family: sans-serif; max-width: 42em; margin: 2em auto; }
+table { width: 100%; border-collapse: collapse; margin-bottom: 1em; }
+th, td { padding: 0.3em 0; text-align: left; }
+th.amount, td.amount { text-align: right; }
+.parties { display: flex; justify-content: space-between; margin: 2em 0; }
+</style>
+</head>
+<body>
+<h1>{{title}} {{invoice_number}}</h1>
+<p>Date: {{date}}<br>Due: {{due}}</p>
+<div class="parties">
+<div><strong>From</strong><br>{{issuer}}</div>
+<div><strong>To</strong><br>{{client}}</div>
+</div>
+<table>
+<tr><th>Description</th><th class="amount">Days</th><th class="amount">Rate</th><th class="amount">Amount</th></tr>
+{{lines}}
+</table>
+<table>
+{{totals}}
+</table>
+<p>{{payment}}</p>
+</body>
+</html>
+"#;
+
+/// Writes the invoice as sent to the client, with the VAT numbers of both sides.
+///
+/// Only the billed amount is shown, the taxes owed on it are none of the client's business.
+pub fn write_invoice_document<W: Write>(
+    mut writer: W,
+    invoice: &Invoice,
+    issuer: &Issuer,
+    client: Option<&Client>,
+    due: NaiveDate,
+    money: &MoneyFormat
+) -> Result<(), InvoiceError> {
+    writeln!(writer, "{} {}", title(invoice), invoice.name)?;
+    writeln!(writer, "Date: {}", invoice.local_date().format("%Y-%m-%d"))?;
+    writeln!(writer, "Due: {}", due)?;
+
+    writeln!(writer)?;
+    writeln!(writer, "From:")?;
+    for line in issuer_lines(issuer) {
+        writeln!(writer, "  {}", line)?;
+    }
+
+    let client_lines = client_lines(invoice, client);
+    if !client_lines.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "To:")?;
+        for line in client_lines {
+            writeln!(writer, "  {}", line)?;
+        }
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "Days worked: {}", invoice.days_worked)?;
+    if invoice.overtime_days > 0.0 {
+        writeln!(writer, "Overtime days: {} at {}x", invoice.overtime_days, invoice.overtime_multiplier)?;
+    }
+    // The client is billed in the currency the rate was agreed in, the EUR value is for the taxes
+    match invoice.original_daily_rate.filter(|_| invoice.currency != "EUR") {
+        Some(original) => writeln!(
+            writer,
+            "Daily rate: {} in {} ({} in EUR)",
+            money.money_in(original, &invoice.currency), invoice.currency, money.money(invoice.daily_rate)
+        )?,
+        None => writeln!(writer, "Daily rate: {}", money.money(invoice.daily_rate))?,
+    }
+    for (label, amount) in totals(invoice, money) {
+        writeln!(writer, "{}: {}", label, amount)?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "{}", payment(invoice, issuer, due, money))?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the invoice as an HTML page filling in `template`, e.g. `HTML_TEMPLATE`, to print to PDF
+/// from a browser. Placeholders the template doesn't know are left as they are.
+pub fn write_invoice_html<W: Write>(
+    mut writer: W,
+    invoice: &Invoice,
+    issuer: &Issuer,
+    client: Option<&Client>,
+    due: NaiveDate,
+    money: &MoneyFormat,
+    template: &str
+) -> Result<(), InvoiceError> {
+    let join = |lines: Vec<String>| lines.iter().map(|line| escape(line)).collect::<Vec<_>>().join("<br>");
+
+    let rate = |rate: Cents| match invoice.original_daily_rate.filter(|_| invoice.currency != "EUR") {
+        Some(original) => format!("{} ({})", money.money_in(original, &invoice.currency), money.money(rate)),
+        None => money.money(rate),
+    };
+    let row = |description: &str, days: f64, daily_rate: String, amount: Cents| {
+        format!(
+            "<tr><td>{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td></tr>",
+            escape(description), days, escape(&daily_rate), escape(&money.money(amount))
+        )
+    };
+    // The overtime line gets what is left of the total, so the lines always add up to it
+    let regular = Invoice::gross_profit(invoice.days_worked, invoice.daily_rate);
+    let mut lines = vec![row("Days worked", invoice.days_worked, rate(invoice.daily_rate), regular)];
+    if invoice.overtime_days > 0.0 {
+        let overtime_rate = (invoice.daily_rate as f64 * invoice.overtime_multiplier).round() as Cents;
+        let description = format!("Overtime at {}x", invoice.overtime_multiplier);
+        let overtime = invoice.gross_profit - regular;
+        lines.push(row(&description, invoice.overtime_days, money.money(overtime_rate), overtime));
+    }
+
+    let totals: Vec<String> = totals(invoice, money)
+        .iter()
+        .map(|(label, amount)| {
+            format!("<tr><th>{}</th><td class=\"amount\">{}</td></tr>", escape(label), escape(amount))
+        })
+        .collect();
+
+    let values = [
+        ("title", escape(title(invoice))),
+        ("invoice_number", escape(&invoice.name)),
+        ("date", invoice.local_date().format("%Y-%m-%d").to_string()),
+        ("due", due.to_string()),
+        ("issuer", join(issuer_lines(issuer))),
+        ("client", join(client_lines(invoice, client))),
+        ("lines", lines.join("\n")),
+        ("totals", totals.join("\n")),
+        ("payment", escape(&payment(invoice, issuer, due, money)))
+    ];
+    writer.write_all(fill(template, &values).as_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn title(invoice: &Invoice) -> &'static str {
+    if invoice.estimated { "Estimate" } else { "Invoice" }
+}
+
+fn issuer_lines(issuer: &Issuer) -> Vec<String> {
+    let mut lines: Vec<String> = issuer.name.iter().cloned().collect();
+    lines.extend(issuer.address.iter().flat_map(|address| address.lines()).map(str::to_string));
+    lines.extend(issuer.vat_id.iter().map(|vat_id| format!("VAT ID: {}", vat_id)));
+
+    lines
+}
+
+/// Name and VAT number recorded on the invoice, with the address of the client from the registry
+fn client_lines(invoice: &Invoice, client: Option<&Client>) -> Vec<String> {
+    let mut lines: Vec<String> = Some(invoice.client.clone()).filter(|name| !name.is_empty()).into_iter().collect();
+    let address = client.and_then(|client| client.address.as_deref());
+    lines.extend(address.into_iter().flat_map(str::lines).map(str::to_string));
+    if !invoice.client_vat_id.is_empty() {
+        lines.push(format!("VAT ID: {}", invoice.client_vat_id));
+    }
+
+    lines
+}
+
+/// Labelled total, VAT and amount due lines
+fn totals(invoice: &Invoice, money: &MoneyFormat) -> Vec<(String, String)> {
+    let mut totals = vec![("Total".to_string(), money.money(invoice.gross_profit))];
+    if invoice.reverse_charge {
+        totals.push(("VAT".to_string(), "reverse charged, to be accounted for by the client".to_string()));
+    } else if invoice.vat > 0 {
+        totals.push((format!("VAT {}%", storage::format_decimal(invoice.vat_rate * 100.0)), money.money(invoice.vat)));
+        totals.push(("Total due".to_string(), money.money(invoice.gross_profit + invoice.vat)));
+    }
+
+    totals
+}
+
+fn payment(invoice: &Invoice, issuer: &Issuer, due: NaiveDate, money: &MoneyFormat) -> String {
+    let amount = money.money(invoice.gross_profit + invoice.vat);
+    match &issuer.iban {
+        Some(iban) => format!("Please pay {} by {} to IBAN {}, mentioning {}", amount, due, iban, invoice.name),
+        None => format!("Please pay {} by {}, mentioning {}", amount, due, invoice.name),
+    }
+}
+
+/// `text` with the characters HTML gives a meaning escaped
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// `template` with each `{{key}}` of `values` replaced in one pass, so a value is never filled in itself
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let key = after[..end].trim();
+            values.iter().find(|(known, _)| *known == key).map(|(_, value)| (value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                filled.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    filled.push_str(rest);
+
+    filled
+}
@@ -0,0 +1,60 @@
+use std::io;
+
+use thiserror::Error;
+
+/// Why a command failed, each kind maps to its own exit code.
+#[derive(Debug, Error)]
+pub enum InvoiceError {
+    /// The input was understood but not accepted, e.g. a duplicate name
+    #[error("{0}")]
+    Validation(String),
+    /// Another invoice of the year already has the name
+    #[error("`name` needs to be unique from other invoices, `{0}` is taken")]
+    DuplicateName(String),
+    /// A value passed in is outside of what `field` accepts
+    #[error("`{field}` {reason}")]
+    InvalidInput { field: &'static str, reason: String },
+    /// A file or stream could not be read or written
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// A file or value could not be understood
+    #[error("{0}")]
+    Parse(String)
+}
+
+impl InvoiceError {
+    /// `field` rejected for `reason`, e.g. `invalid("days_worked", "needs to be more than 0")`
+    pub fn invalid(field: &'static str, reason: impl Into<String>) -> Self {
+        InvoiceError::InvalidInput { field, reason: reason.into() }
+    }
+
+    /// Exit code the process ends with: 1 validation, 2 I/O, 3 parse
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            InvoiceError::Validation(_) | InvoiceError::DuplicateName(_) | InvoiceError::InvalidInput { .. } => 1,
+            InvoiceError::Io(_) => 2,
+            InvoiceError::Parse(_) => 3,
+        }
+    }
+}
+
+// csv and serde_json errors are either I/O or malformed input, so they are split by kind
+impl From<csv::Error> for InvoiceError {
+    fn from(err: csv::Error) -> Self {
+        let message = err.to_string();
+
+        match err.into_kind() {
+            csv::ErrorKind::Io(err) => InvoiceError::Io(err),
+            _ => InvoiceError::Parse(message),
+        }
+    }
+}
+
+impl From<serde_json::Error> for InvoiceError {
+    fn from(err: serde_json::Error) -> Self {
+        match err.io_error_kind() {
+            Some(kind) => InvoiceError::Io(io::Error::new(kind, err)),
+            None => InvoiceError::Parse(err.to_string()),
+        }
+    }
+}
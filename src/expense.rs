@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::cents::{self, Cents};
+use crate::Invoice;
+
+/// A professional expense, e.g. a laptop or the accountant's fee, deducted from the gross profit
+/// before the brackets apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expense {
+    pub name: String,
+    pub date: NaiveDate,
+    /// Amount paid in EUR
+    #[serde(rename = "amount_cents")]
+    pub amount: Cents,
+    /// Part of the amount that is deductible, e.g. `0.75` for a car also used privately
+    pub deductible_share: f64,
+    /// Tax profile the expense is deducted under, empty for the default regime
+    #[serde(default)]
+    pub profile: String
+}
+
+impl Expense {
+    /// Part of the amount deducted from the gross profit
+    pub fn deductible(&self) -> Cents {
+        cents::apply_rate(self.amount, self.deductible_share)
+    }
+}
+
+/// Deductible expenses of a year handed out to its invoices in date order. Each invoice is taxed
+/// on what is left after the expenses dated up to it that earlier invoices haven't used up.
+///
+/// An expense is never deducted from income invoiced before it, taxes already reckoned stay as they
+/// were. One dated after the last invoice waits for the next invoice, what the year's invoices don't
+/// use up is not deducted at all.
+pub(crate) struct Deductions {
+    /// Date and deductible amount of each expense, oldest first
+    expenses: Vec<(NaiveDate, Cents)>,
+    next: usize,
+    available: Cents
+}
+
+impl Deductions {
+    /// Deductions of the expenses of `profile`
+    pub(crate) fn new(expenses: &[Expense], profile: &str) -> Deductions {
+        let mut dated: Vec<(NaiveDate, Cents)> = expenses
+            .iter()
+            .filter(|expense| expense.profile == profile)
+            .map(|expense| (expense.date, expense.deductible()))
+            .collect();
+        dated.sort_by_key(|(date, _)| *date);
+
+        Deductions { expenses: dated, next: 0, available: 0 }
+    }
+
+    /// Part of `gross_profit` of an invoice dated `date` the expenses so far cover, which it uses up.
+    /// Invoices have to be taken in date order.
+    pub(crate) fn take(&mut self, date: NaiveDate, gross_profit: Cents) -> Cents {
+        while let Some((_, amount)) = self.expenses.get(self.next).filter(|(expense_date, _)| *expense_date <= date) {
+            self.available += amount;
+            self.next += 1;
+        }
+        let deduction = self.available.min(gross_profit).max(0);
+        self.available -= deduction;
+
+        deduction
+    }
+}
+
+/// Taxable profit of each of `invoices`, in their order: the gross profit less the deduction the
+/// expenses of its profile leave for it, handed out in date order as the taxes are reckoned.
+pub(crate) fn taxable_profits(invoices: &[Invoice], expenses: &[Expense]) -> Vec<Cents> {
+    let mut order: Vec<usize> = (0..invoices.len()).collect();
+    order.sort_by_key(|&index| invoices[index].date);
+
+    let mut deductions: HashMap<&str, Deductions> = HashMap::new();
+    let mut taxable_profits = vec![0; invoices.len()];
+    for index in order {
+        let invoice = &invoices[index];
+        let deduction = deductions
+            .entry(invoice.profile.as_str())
+            .or_insert_with(|| Deductions::new(expenses, &invoice.profile))
+            .take(invoice.local_date().date_naive(), invoice.gross_profit);
+        taxable_profits[index] = invoice.gross_profit - deduction;
+    }
+
+    taxable_profits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expense(date: NaiveDate, amount: Cents, profile: &str) -> Expense {
+        Expense { name: "laptop".to_string(), date, amount, deductible_share: 1.0, profile: profile.to_string() }
+    }
+
+    fn ymd(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    #[test]
+    fn expense_is_only_deducted_from_invoices_dated_on_or_after_it() {
+        let mut deductions = Deductions::new(&[expense(ymd(3, 1), 100_000, "")], "");
+
+        assert_eq!(deductions.take(ymd(2, 28), 50_000), 0);
+        assert_eq!(deductions.take(ymd(3, 1), 60_000), 60_000);
+        // What the first invoice after it didn't use up goes to the next one
+        assert_eq!(deductions.take(ymd(4, 1), 60_000), 40_000);
+        assert_eq!(deductions.take(ymd(5, 1), 60_000), 0);
+    }
+
+    #[test]
+    fn expense_after_the_last_invoice_is_not_deducted() {
+        let mut deductions = Deductions::new(&[expense(ymd(12, 20), 100_000, "")], "");
+
+        assert_eq!(deductions.take(ymd(12, 1), 50_000), 0);
+    }
+
+    #[test]
+    fn only_the_deductible_share_of_the_profile_is_deducted() {
+        let shared = Expense { deductible_share: 0.75, ..expense(ymd(1, 1), 100_000, "") };
+        let other_profile = expense(ymd(1, 1), 100_000, "company");
+        let mut deductions = Deductions::new(&[shared, other_profile], "");
+
+        assert_eq!(deductions.take(ymd(6, 1), 500_000), 75_000);
+    }
+}
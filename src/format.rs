@@ -0,0 +1,185 @@
+use clap::ValueEnum;
+use num_format::{CustomFormat, Error, Grouping, Locale, ToFormattedString};
+use serde::Deserialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::cents::{self, Cents};
+
+/// Whether and where the currency is shown next to an amount.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CurrencyStyle {
+    /// `1,234.00`
+    #[default]
+    None,
+    /// `€1,234.00`
+    Symbol,
+    /// `1,234.00 €`
+    SymbolAfter,
+    /// `EUR 1,234.00`
+    Code,
+    /// `1,234.00 EUR`
+    CodeAfter
+}
+
+/// Symbol of common ISO 4217 currency codes
+pub fn currency_symbol(code: &str) -> Option<&'static str> {
+    let symbol = match code {
+        "EUR" => "€",
+        "USD" => "$",
+        "GBP" => "£",
+        "JPY" => "¥",
+        "CNY" => "¥",
+        "INR" => "₹",
+        "KRW" => "₩",
+        "BRL" => "R$",
+        "PLN" => "zł",
+        "TRY" => "₺",
+        "ILS" => "₪",
+        "UAH" => "₴",
+        _ => return None,
+    };
+
+    Some(symbol)
+}
+
+/// How monetary amounts are displayed, stored values are never affected by it.
+#[derive(Debug, Clone)]
+pub struct MoneyFormat {
+    format: CustomFormat,
+    precision: usize,
+    currency_style: CurrencyStyle
+}
+
+impl MoneyFormat {
+    pub const PRECISION: usize = 2;
+    /// Currency the totals are expressed in
+    const CURRENCY: &'static str = "EUR";
+
+    /// No thousands separator and a `.` decimal mark, e.g. `1234.56`
+    pub fn neutral() -> Self {
+        let format = CustomFormat::builder()
+            .grouping(Grouping::Posix)
+            .separator("")
+            .decimal(".")
+            .minus_sign("-")
+            .build()
+            .unwrap();
+
+        MoneyFormat { format, precision: Self::PRECISION, currency_style: CurrencyStyle::None }
+    }
+
+    /// Separators of a locale by name, e.g. `de` gives `1.234,56` and `en` gives `1,234.56`
+    pub fn from_locale(name: &str) -> Result<Self, Error> {
+        let locale = Locale::from_name(name)?;
+        let format = CustomFormat::builder()
+            .grouping(locale.grouping())
+            .separator(locale.separator())
+            .decimal(locale.decimal())
+            .minus_sign(locale.minus_sign())
+            .build()?;
+
+        Ok(MoneyFormat { format, precision: Self::PRECISION, currency_style: CurrencyStyle::None })
+    }
+
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn with_currency_style(mut self, currency_style: CurrencyStyle) -> Self {
+        self.currency_style = currency_style;
+        self
+    }
+
+    pub fn money(&self, amount: Cents) -> String {
+        self.money_in(amount, Self::CURRENCY)
+    }
+
+    /// Formats an amount in `currency`, unknown symbols fall back to the ISO code
+    pub fn money_in(&self, amount: Cents, currency: &str) -> String {
+        let number = self.number(cents::to_amount(amount));
+        let symbol = currency_symbol(currency).unwrap_or(currency);
+
+        match self.currency_style {
+            CurrencyStyle::None => number,
+            CurrencyStyle::Symbol if symbol == currency => format!("{} {}", currency, number),
+            CurrencyStyle::Symbol => format!("{}{}", symbol, number),
+            CurrencyStyle::SymbolAfter => format!("{} {}", number, symbol),
+            CurrencyStyle::Code => format!("{} {}", currency, number),
+            CurrencyStyle::CodeAfter => format!("{} {}", number, currency),
+        }
+    }
+
+    fn number(&self, amount: f64) -> String {
+        let rounded = format!("{:.*}", self.precision, amount.abs());
+        let (whole, fraction) = rounded.split_once('.').unwrap_or((&rounded, ""));
+        let whole: u64 = whole.parse().unwrap_or(0);
+
+        let mut formatted = String::new();
+        if amount < 0.0 && rounded.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            formatted.push_str(self.format.minus_sign());
+        }
+        formatted.push_str(&whole.to_formatted_string(&self.format));
+        if !fraction.is_empty() {
+            formatted.push_str(self.format.decimal());
+            formatted.push_str(fraction);
+        }
+
+        formatted
+    }
+}
+
+impl Default for MoneyFormat {
+    fn default() -> Self {
+        Self::neutral()
+    }
+}
+
+/// Terminal columns `text` takes, two for a wide character like `株` and none for a combining one
+pub fn display_width(text: &str) -> usize {
+    text.width()
+}
+
+/// `text` followed by spaces up to `width` columns, for left-aligned table cells
+pub fn pad_end(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(display_width(text))))
+}
+
+/// `text` preceded by spaces up to `width` columns, for right-aligned table cells
+pub fn pad_start(text: &str, width: usize) -> String {
+    format!("{}{}", " ".repeat(width.saturating_sub(display_width(text))), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        assert_eq!(display_width("株式会社"), 8);
+        assert_eq!(display_width("Müller"), 6);
+        assert_eq!(pad_end("株式会社", 10), "株式会社  ");
+        assert_eq!(pad_start("株", 4), "  株");
+    }
+
+    #[test]
+    fn combining_characters_take_no_column() {
+        // `u` followed by a combining diaeresis is one column, though two chars and three bytes
+        let decomposed = "Mu\u{308}ller";
+        assert_eq!(display_width(decomposed), 6);
+        assert_eq!(pad_end(decomposed, 8), format!("{}  ", decomposed));
+    }
+
+    #[test]
+    fn columns_line_up_whatever_the_script() {
+        let names = ["Müller", "株式会社", "Mu\u{308}ller", "Acme"];
+        let width = names.iter().map(|name| display_width(name)).max().unwrap();
+
+        for name in names {
+            assert_eq!(display_width(&pad_end(name, width)), 8);
+        }
+        // Text already wider than the column is left as it is
+        assert_eq!(pad_end("株式会社", 4), "株式会社");
+    }
+}
@@ -0,0 +1,82 @@
+use std::cell::RefCell;
+use std::io;
+use std::process::Command;
+use std::rc::Rc;
+
+use crate::{note, Notes};
+
+/// Shell command run after each write of an invoice file, `on_write` of the config
+#[derive(Clone)]
+pub struct OnWrite {
+    command: Option<String>,
+    /// Where a failing command is reported
+    notes: Notes
+}
+
+impl Default for OnWrite {
+    fn default() -> Self {
+        OnWrite { command: None, notes: Rc::new(RefCell::new(io::sink())) }
+    }
+}
+
+impl OnWrite {
+    /// Runs `command`, if one is given, after every invoice file a store with it writes
+    pub fn new(command: Option<String>, notes: Notes) -> OnWrite {
+        OnWrite { command, notes }
+    }
+
+    /// Runs the command, if one is set, on the file at `path` that was just written.
+    ///
+    /// The path is passed as `$1` and in `ACCOUNTANT_TOOL_FILE`, the output goes to stderr so it never
+    /// mixes with an export on stdout. The write already happened, so a failing command is only reported.
+    pub(crate) fn written(&self, path: &str) {
+        if let Some(warning) = self.command.as_ref().and_then(|command| run(command, path)) {
+            note(&self.notes, &warning);
+        }
+    }
+}
+
+/// Runs `command` on `path`, with a warning to report if it failed
+fn run(command: &str, path: &str) -> Option<String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command, "sh", path]);
+        shell
+    };
+    let status = shell.env("ACCOUNTANT_TOOL_FILE", path).stdout(io::stderr()).status();
+
+    match status {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("Warning: the `on_write` command failed for {} with {}", path, status)),
+        Err(err) => Some(format!("Warning: can not run the `on_write` command for {}: {}", path, err)),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn command_gets_the_path_of_the_written_file() {
+        let log = std::env::temp_dir().join(format!("accountant_tool_hook_{}.log", std::process::id()));
+        let command = format!("echo \"$1 $ACCOUNTANT_TOOL_FILE\" > '{}'", log.display());
+
+        assert_eq!(run(&command, "src/invoices_2024.csv"), None);
+        assert_eq!(fs::read_to_string(&log).unwrap(), "src/invoices_2024.csv src/invoices_2024.csv\n");
+
+        fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn failing_command_is_only_reported() {
+        let warning = run("exit 3", "src/invoices_2024.csv").unwrap();
+        assert!(warning.starts_with("Warning: the `on_write` command failed for src/invoices_2024.csv"));
+        assert!(run("a_command_that_does_not_exist_anywhere", "src/invoices_2024.csv").is_some());
+    }
+}
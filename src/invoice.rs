@@ -0,0 +1,547 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::prelude::{DateTime, Local};
+use chrono::{Datelike, NaiveDate, TimeZone};
+
+use crate::cents::{self, Cents};
+use crate::config::{Config, TaxRules};
+use crate::error::InvoiceError;
+use crate::expense::Deductions;
+use crate::format::MoneyFormat;
+use crate::rates::{self, RateProvider};
+use crate::tax;
+use crate::Context;
+
+/// Invoice to record with `Invoice::new`, what is left out falls back on the config or the defaults
+#[derive(Debug, Clone)]
+pub struct NewInvoice {
+    pub name: String,
+    pub days_worked: f64,
+    /// Days worked on top of `days_worked`, billed at `overtime_multiplier` times the daily rate
+    pub overtime_days: f64,
+    pub overtime_multiplier: f64,
+    /// In `currency`, converted to EUR for the taxes
+    pub daily_rate: Option<f64>,
+    pub currency: Option<String>,
+    pub client: Option<String>,
+    pub client_vat_id: Option<String>,
+    pub vat_rate: Option<f64>,
+    /// Charge no VAT, the client accounts for it
+    pub reverse_charge: bool,
+    /// The days are not final yet
+    pub estimated: bool,
+    /// Day the work was invoiced on, now when left out
+    pub date: Option<NaiveDate>,
+    /// Year whose file a late entry is added to, whatever year it is dated in
+    pub append_to: Option<i32>,
+    /// Year selected with `--year`, which the invoice has to be dated in
+    pub selected_year: Option<i32>,
+    /// Record it even if the same work was recorded under another name
+    pub force: bool
+}
+
+impl NewInvoice {
+    /// `days_worked` at the configured daily rate, without overtime
+    pub fn new(name: String, days_worked: f64) -> NewInvoice {
+        NewInvoice {
+            name,
+            days_worked,
+            overtime_days: 0.0,
+            overtime_multiplier: 1.0,
+            daily_rate: None,
+            currency: None,
+            client: None,
+            client_vat_id: None,
+            vat_rate: None,
+            reverse_charge: false,
+            estimated: false,
+            date: None,
+            append_to: None,
+            selected_year: None,
+            force: false
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Invoice {
+    pub(crate) name: String,
+    pub(crate) date: u128,
+    pub(crate) days_worked: f64,
+    pub(crate) daily_rate: Cents,
+    pub(crate) currency: String,
+    /// Daily rate in `currency` as it was entered, `daily_rate` is its EUR value. `None` for invoices
+    /// recorded before it was kept.
+    pub(crate) original_daily_rate: Option<Cents>,
+    pub(crate) client: String,
+    /// VAT number of the client, only shown on the rendered invoice
+    pub(crate) client_vat_id: String,
+    /// Recorded before the days are final, e.g. to plan with
+    pub(crate) estimated: bool,
+    /// Day the client paid, `None` while the invoice is outstanding
+    pub(crate) paid_date: Option<NaiveDate>,
+    /// Tax profile the invoice is taxed under, empty for the default regime
+    pub(crate) profile: String,
+    /// Days worked on top of `days_worked`, billed at the daily rate times `overtime_multiplier`
+    pub(crate) overtime_days: f64,
+    pub(crate) overtime_multiplier: f64,
+    /// Share of the billed amount charged as VAT on top of it, e.g. `0.21`
+    pub(crate) vat_rate: f64,
+    /// VAT charged on top of the gross profit, it is owed to the state and no part of the income
+    pub(crate) vat: Cents,
+    /// The client accounts for the VAT instead, e.g. a business in another EU country
+    pub(crate) reverse_charge: bool,
+    pub(crate) gross_profit: Cents,
+    pub(crate) net_profit: Cents,
+    pub(crate) government_tax: Cents,
+    pub(crate) social_contribution_tax: Cents,
+    pub(crate) total_tax: Cents
+}
+
+impl Invoice {
+    /// Daily rate of a new invoice when neither the command nor the config gives one
+    pub const DAILY_RATE: f64 = 500.0;
+    /// Currency of a new invoice when neither the command nor the config gives one
+    pub const CURRENCY: &'static str = "EUR";
+    /// VAT rate of a new invoice when neither the command nor the config gives one, none as for the
+    /// small business exemption
+    pub const VAT_RATE: f64 = 0.0;
+    /// Days without an invoice after which `gaps` reports the stretch, unless configured
+    pub const GAP_DAYS: i64 = 30;
+    /// Hours in a worked day, for showing the days as hours, unless configured
+    pub const HOURS_PER_DAY: f64 = 8.0;
+    /// Days ahead of today a new invoice can at most be dated, unless configured
+    pub const FUTURE_DAYS: i64 = 7;
+
+    pub(crate) fn local_date(&self) -> DateTime<Local> {
+        Local.timestamp_millis_opt(self.date as i64).unwrap()
+    }
+
+    /// Checks that the amounts of the invoice add up before it is written, so a mistake in computing
+    /// them is caught before it reaches the file. A zero gross profit is accepted as not computed
+    /// yet, e.g. of an imported invoice `backfill` fills in later.
+    pub(crate) fn check_invariants(&self) -> Result<(), InvoiceError> {
+        let invalid = |reason: String| {
+            Err(InvoiceError::Validation(format!("invoice `{}` is inconsistent: {}", self.name, reason)))
+        };
+
+        for (column, value, minimum) in [
+            ("days_worked", self.days_worked, 0.0),
+            ("overtime_days", self.overtime_days, 0.0),
+            ("overtime_multiplier", self.overtime_multiplier, 1.0),
+            ("vat_rate", self.vat_rate, 0.0)
+        ] {
+            if !value.is_finite() || value < minimum {
+                return invalid(format!("{} is {}", column, value));
+            }
+        }
+        for (column, value) in [
+            ("gross_profit", self.gross_profit),
+            ("vat", self.vat),
+            ("government_tax", self.government_tax),
+            ("social_contribution_tax", self.social_contribution_tax),
+            ("total_tax", self.total_tax)
+        ] {
+            if value < 0 {
+                return invalid(format!("{} is negative", column));
+            }
+        }
+
+        if self.total_tax != self.government_tax + self.social_contribution_tax {
+            return invalid("total_tax is not the sum of the government tax and social contribution".to_string());
+        }
+        // Rounding the days times the rate can be off by a cent at most
+        let billed = self.billed_gross_profit();
+        if self.gross_profit != 0 && (self.gross_profit - billed).abs() > 1 {
+            return invalid(format!(
+                "gross_profit {:.2} does not match the days at the daily rate, {:.2}",
+                cents::to_amount(self.gross_profit), cents::to_amount(billed)
+            ));
+        }
+        if self.reverse_charge && self.vat_rate != 0.0 {
+            return invalid(format!("vat_rate is {} on a reverse-charged invoice", self.vat_rate));
+        }
+        if self.gross_profit != 0 && (self.vat - self.billed_vat()).abs() > 1 {
+            return invalid(format!(
+                "vat {:.2} is not the VAT rate of the billed amount, {:.2}",
+                cents::to_amount(self.vat), cents::to_amount(self.billed_vat())
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Amount billed for `days_worked` at `daily_rate`, rounded to the nearest cent
+    pub(crate) fn gross_profit(days_worked: f64, daily_rate: Cents) -> Cents {
+        (days_worked * daily_rate as f64).round() as Cents
+    }
+
+    /// Amount billed for the regular and overtime days of the invoice
+    pub(crate) fn billed_gross_profit(&self) -> Cents {
+        Self::gross_profit(self.days_worked + self.overtime_days * self.overtime_multiplier, self.daily_rate)
+    }
+
+    /// VAT charged on top of the billed amount
+    pub(crate) fn billed_vat(&self) -> Cents {
+        cents::apply_rate(self.billed_gross_profit(), self.vat_rate)
+    }
+
+    /// Whether `days_worked` is a whole number of `increment`s, e.g. quarter days for `0.25`
+    pub(crate) fn is_billing_multiple(days_worked: f64, increment: f64) -> bool {
+        let increments = days_worked / increment;
+
+        (increments - increments.round()).abs() < 1e-9
+    }
+
+    /// Year whose file an invoice dated `date` goes to: its own, or with `append_to` that one, e.g. for
+    /// January work of the last fiscal year. A `selected_year` only picks the file, the invoice still
+    /// has to be dated in it.
+    fn filed_year(
+        date: NaiveDate,
+        today: NaiveDate,
+        append_to: Option<i32>,
+        selected_year: Option<i32>
+    ) -> Result<i32, InvoiceError> {
+        let year = date.year();
+        match (append_to, selected_year) {
+            (Some(append_to), _) => {
+                if !(today.year() - 10..=today.year()).contains(&append_to) {
+                    return Err(InvoiceError::Validation(format!(
+                        "`--append-to` needs to be one of the last ten years up to {}", today.year()
+                    )));
+                }
+                Ok(append_to)
+            }
+            (None, Some(selected_year)) if selected_year != year => Err(InvoiceError::Validation(format!(
+                "`--year {}` files the invoice in {} but it is dated {}, pass a `--date` in {} or \
+                `--append-to {}` to file it there anyway",
+                selected_year, selected_year, date, selected_year, selected_year
+            ))),
+            _ => Ok(year),
+        }
+    }
+
+    /// Records the invoice `new` describes in the file of the year it is filed in
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        context: &Context,
+        new: NewInvoice,
+        config: &Config,
+        rules: &TaxRules,
+        rates: &dyn RateProvider,
+        money: &MoneyFormat,
+        quiet: bool
+    ) -> Result<(), InvoiceError> {
+        let NewInvoice {
+            name,
+            days_worked,
+            overtime_days,
+            overtime_multiplier,
+            daily_rate,
+            currency,
+            client,
+            client_vat_id,
+            vat_rate,
+            reverse_charge,
+            estimated,
+            date,
+            append_to,
+            selected_year,
+            force
+        } = new;
+        let today = Local::now().date_naive();
+        let future_days = config.future_days.unwrap_or(Self::FUTURE_DAYS);
+        if date.is_some_and(|date| (date - today).num_days() > future_days) {
+            return Err(InvoiceError::invalid(
+                "date", format!("can not be more than {} days from today, see `future_days` in the config", future_days)
+            ));
+        }
+
+        // An invoice dated today gets the current time, one on another day noon of that day
+        let timestamp_millis = match date.filter(|date| *date != today) {
+            Some(date) => Local.from_local_datetime(&date.and_hms_opt(12, 0, 0).unwrap())
+                .earliest()
+                .ok_or_else(|| InvoiceError::Validation(format!("{} has no local noon", date)))?
+                .timestamp_millis() as u128,
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| InvoiceError::Validation(format!("the system clock is before 1970: {}", err)))?
+                .as_millis(),
+        };
+        let dated_year = date.unwrap_or(today).year();
+        let year = Self::filed_year(date.unwrap_or(today), today, append_to, selected_year)?;
+        if year != dated_year && !quiet {
+            context.note(&format!("Warning: the invoice is dated in {} but added to the file of {}", dated_year, year));
+        }
+
+        let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+        invoices.sort_by_key(|invoice| invoice.date);
+        let daily_rate = daily_rate.or(config.daily_rate);
+        let currency = currency.or(config.currency.clone());
+        // A client of the registry is recorded under its name, with its VAT number unless another is passed
+        let registered = client.as_deref().and_then(|client| config.clients.get(client));
+        let client_vat_id = client_vat_id.or(registered.and_then(|registered| registered.vat_id.clone()));
+        let client = registered.and_then(|registered| registered.name.clone()).or(client);
+        
+        if invoices.iter().any(|invoice| invoice.name == name) {
+            return Err(InvoiceError::DuplicateName(name));
+        } else if days_worked <= 0.0 || !days_worked.is_finite() {
+            return Err(InvoiceError::invalid("days_worked", "needs to be more than 0"));
+        } else if overtime_days < 0.0 || !overtime_days.is_finite() {
+            return Err(InvoiceError::invalid("overtime_days", "can not be negative"));
+        } else if overtime_multiplier < 1.0 || !overtime_multiplier.is_finite() {
+            return Err(InvoiceError::invalid("overtime_multiplier", "needs to be at least 1"));
+        } else if daily_rate == Some(0.0) && !config.allow_zero_rate {
+            return Err(InvoiceError::invalid(
+                "daily_rate", "can not be 0.0, set `allow_zero_rate` in the config for pro-bono work"
+            ));
+        } else if reverse_charge && vat_rate.is_some() {
+            return Err(InvoiceError::Validation("a reverse-charged invoice has no `vat_rate`".to_string()));
+        } else if reverse_charge && client_vat_id.as_deref().unwrap_or_default().is_empty() {
+            return Err(InvoiceError::Validation(
+                "reverse charging needs the VAT number of the client, pass `--client-vat-id`".to_string()
+            ));
+        }
+        // The client pays the VAT of a reverse-charged invoice to their own state, none is charged
+        let vat_rate = if reverse_charge { 0.0 } else { vat_rate.or(config.vat_rate).unwrap_or(Self::VAT_RATE) };
+        if !(0.0..1.0).contains(&vat_rate) {
+            return Err(InvoiceError::invalid("vat_rate", "needs to be at least 0 and below 1"));
+        }
+
+        if let Some(increment) = config.billing_increment {
+            if !Self::is_billing_multiple(days_worked, increment) {
+                return Err(InvoiceError::invalid(
+                    "days_worked", format!("needs to be a multiple of the billing increment {}", increment)
+                ));
+            }
+        }
+
+        let daily_rate = daily_rate.unwrap_or(Self::DAILY_RATE);
+        let currency = currency.unwrap_or(Self::CURRENCY.to_string()).to_uppercase();
+
+        if !rates::is_known_currency(&currency, &config.custom_currencies) {
+            return Err(InvoiceError::invalid("currency", format!(
+                "needs to be a recognized ISO 4217 code, add `{}` to `custom_currencies` to allow it", currency
+            )));
+        }
+
+        // The brackets are in EUR, so the rate is converted before any tax is computed
+        let original_daily_rate = cents::from_amount(daily_rate);
+        let daily_rate = cents::from_amount(rates::convert_to_eur(rates, daily_rate, &currency)?);
+        let client = client.unwrap_or_default();
+
+        // The same work recorded again under another name is most likely the same command run twice
+        let duplicate = invoices.iter().find(|invoice| {
+            invoice.client == client
+                && invoice.days_worked == days_worked
+                && invoice.overtime_days == overtime_days
+                && invoice.overtime_multiplier == overtime_multiplier
+                && invoice.daily_rate == daily_rate
+                && invoice.currency == currency
+        });
+        if let (Some(duplicate), false) = (duplicate, force) {
+            return Err(InvoiceError::Validation(format!(
+                "invoice `{}` already has the same client, days, rate and currency, pass `--force` to record it anyway",
+                duplicate.name
+            )));
+        }
+
+        // The invoice is taxed on top of the ones dated before it, a later one is recalculated below
+        let position = invoices.partition_point(|invoice| invoice.date <= timestamp_millis);
+        // The brackets of the year it is filed with, those in effect at its end when that is another year
+        let tax_date = Some(date.unwrap_or(today))
+            .filter(|tax_date| tax_date.year() == year)
+            .unwrap_or_else(|| NaiveDate::from_ymd_opt(year, 12, 31).unwrap());
+        let tax_buckets = tax::tax_buckets(&rules.brackets, tax_date);
+        let prior_gross_profit = tax::profile_gross_profit(&invoices[..position], &rules.profile);
+        let expenses = context.fetch_year_expenses(year)?;
+        let mut deductions = Deductions::new(&expenses, &rules.profile);
+        let prior_taxable_profit = tax::profile_taxable_profit(&invoices[..position], &rules.profile, &mut deductions);
+        let gross_profit = Self::gross_profit(days_worked + overtime_days * overtime_multiplier, daily_rate);
+        let deduction = deductions.take(date.unwrap_or(today), gross_profit);
+        let (gross_profit, net_profit, government_tax, social_contribution_tax) =
+            tax::calc_taxes(gross_profit, prior_taxable_profit, deduction, &tax_buckets, rules);
+
+        if !quiet {
+            if let Some((top_gross_profit, tax)) =
+                tax::top_bracket_gross_profit(&tax_buckets, prior_taxable_profit, gross_profit - deduction) {
+                context.note(&format!(
+                    "This invoice is taxed at up to {}%, {} of it is in the top bracket",
+                    tax * 100.0, money.money(top_gross_profit)
+                ));
+            }
+        }
+
+        let invoice = Invoice {
+            name,
+            date: timestamp_millis,
+            days_worked,
+            daily_rate,
+            currency,
+            original_daily_rate: Some(original_daily_rate),
+            client,
+            client_vat_id: client_vat_id.unwrap_or_default(),
+            estimated,
+            paid_date: None,
+            profile: rules.profile.clone(),
+            overtime_days,
+            overtime_multiplier,
+            vat_rate,
+            vat: cents::apply_rate(gross_profit, vat_rate),
+            reverse_charge,
+            gross_profit,
+            net_profit,
+            government_tax,
+            social_contribution_tax,
+            total_tax: government_tax + social_contribution_tax
+        };
+
+        let later = invoices.len() - position;
+        // The minimum social contribution is owed on the year as a whole, so its top-up is spread over
+        // the invoices again with each one added, whatever order they are recorded in
+        let (net_profit, total_tax) = if later == 0 && rules.minimum_social_contribution == 0 {
+            let taxes = (invoice.net_profit, invoice.total_tax);
+            context.append_invoice(year, invoice)?;
+            taxes
+        } else {
+            invoice.check_invariants()?;
+            invoices.insert(position, invoice);
+            tax::recalculate_year(&mut invoices, &expenses, rules);
+            context.save_year_invoices(year, &invoices)?;
+            (invoices[position].net_profit, invoices[position].total_tax)
+        };
+
+        if !quiet {
+            if later > 0 {
+                context.note(&format!("Recalculated the {} invoices of {} dated after it", later, year));
+            }
+            let year_gross_profit = prior_gross_profit + gross_profit;
+            let year_taxable_profit = prior_taxable_profit + gross_profit - deduction;
+            writeln!(
+                context.out(),
+                "Gross {}, net {}, taxes {}",
+                money.money(gross_profit),
+                money.money(net_profit),
+                money.money(total_tax)
+            )?;
+            writeln!(context.out(), "Gross so far in {}: {}", year, money.money(year_gross_profit))?;
+            if year_taxable_profit != year_gross_profit {
+                writeln!(
+                    context.out(),
+                    "Taxable so far in {}: {} after expenses", year, money.money(year_taxable_profit)
+                )?;
+            }
+            writeln!(
+                context.out(),
+                "Marginal tax rate: {:.2}%",
+                tax::marginal_tax_rate(&tax_buckets, rules, year_taxable_profit) * 100.0
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::hook::OnWrite;
+    use crate::storage::CsvStore;
+    use crate::tests::{context, csv_store, in_temp_dir, invoice, ymd};
+
+    #[test]
+    fn rejected_invoices_say_which_input_was_wrong() {
+        in_temp_dir("rejected", || {
+            let context = context(csv_store(), 2025);
+            let recorded = |new: NewInvoice| {
+                let config = Config::default();
+                let rules = config.tax_rules(None).unwrap();
+                let rates = rates::StaticRates::default();
+                Invoice::new(&context, new, &config, &rules, &rates, &MoneyFormat::neutral(), true)
+            };
+            let dated = |name: &str, days_worked: f64| NewInvoice {
+                date: Some(ymd(2025, 3, 1)),
+                ..NewInvoice::new(name.to_string(), days_worked)
+            };
+
+            recorded(dated("2025-001", 5.0)).unwrap();
+            let err = recorded(dated("2025-001", 3.0)).unwrap_err();
+            assert!(matches!(&err, InvoiceError::DuplicateName(name) if name == "2025-001"));
+            assert_eq!(err.exit_code(), 1);
+
+            let err = recorded(dated("2025-002", 0.0)).unwrap_err();
+            assert!(matches!(err, InvoiceError::InvalidInput { field: "days_worked", .. }));
+            assert_eq!(err.to_string(), "`days_worked` needs to be more than 0");
+        });
+    }
+
+    #[test]
+    fn minimum_social_contribution_does_not_depend_on_the_order_invoices_are_recorded_in() {
+        in_temp_dir("minimum", || {
+            let config = Config::default();
+            let rules = TaxRules { minimum_social_contribution: 500_000, ..config.tax_rules(None).unwrap() };
+            let recorded = |dir: &str, order: [(&str, u32, f64); 3]| {
+                let context = context(CsvStore::new(PathBuf::from(dir), OnWrite::default()), 2025);
+                for (name, month, days_worked) in order {
+                    let new = NewInvoice {
+                        date: Some(ymd(2025, month, 1)),
+                        ..NewInvoice::new(name.to_string(), days_worked)
+                    };
+                    let rates = rates::StaticRates::default();
+                    Invoice::new(&context, new, &config, &rules, &rates, &MoneyFormat::neutral(), true).unwrap();
+                }
+                let invoices = context.fetch_invoices().unwrap();
+                invoices.iter().map(|invoice| (invoice.name.clone(), invoice.total_tax)).collect::<Vec<_>>()
+            };
+
+            let in_order = recorded("in_order", [("a", 3, 5.0), ("b", 6, 3.0), ("c", 9, 2.0)]);
+            let out_of_order = recorded("out_of_order", [("c", 9, 2.0), ("a", 3, 5.0), ("b", 6, 3.0)]);
+            assert_eq!(in_order, out_of_order);
+        });
+    }
+
+    #[test]
+    fn back_dated_invoice_is_filed_in_the_year_it_is_dated_in() {
+        let today = ymd(2024, 2, 10);
+
+        assert_eq!(Invoice::filed_year(ymd(2023, 12, 20), today, None, None).unwrap(), 2023);
+        assert_eq!(Invoice::filed_year(ymd(2023, 12, 20), today, None, Some(2023)).unwrap(), 2023);
+        assert_eq!(csv_store().year_file_path(2023), "invoices/invoices_2023.csv");
+    }
+
+    #[test]
+    fn selected_year_has_to_match_the_date() {
+        let err = Invoice::filed_year(ymd(2023, 12, 20), ymd(2024, 2, 10), None, Some(2024)).unwrap_err();
+        assert!(err.to_string().starts_with("`--year 2024` files the invoice in 2024 but it is dated 2023-12-20"));
+    }
+
+    #[test]
+    fn append_to_files_a_late_entry_in_a_recent_year() {
+        let today = ymd(2024, 2, 10);
+
+        assert_eq!(Invoice::filed_year(ymd(2024, 1, 5), today, Some(2023), Some(2024)).unwrap(), 2023);
+        assert!(Invoice::filed_year(ymd(2024, 1, 5), today, Some(2013), None).is_err());
+        assert!(Invoice::filed_year(ymd(2024, 1, 5), today, Some(2025), None).is_err());
+    }
+
+    #[test]
+    fn overtime_days_are_billed_at_the_multiplier() {
+        let daily_rate = invoice(ymd(2024, 5, 1), 50_000);
+        let overtime = Invoice { days_worked: 3.0, overtime_days: 2.0, overtime_multiplier: 1.5, ..daily_rate.clone() };
+        assert_eq!(overtime.billed_gross_profit(), 3 * 50_000 + 3 * 50_000);
+
+        let without = Invoice { days_worked: 3.0, ..daily_rate };
+        assert_eq!(without.billed_gross_profit(), 150_000);
+    }
+
+    #[test]
+    fn partial_days_are_rounded_to_the_cent() {
+        assert_eq!(Invoice::gross_profit(0.5, 33_333), 16_667);
+        // 2.5 regular days and half a day at 1.25x bill 3.125 days
+        let daily_rate = invoice(ymd(2024, 5, 1), 33_333);
+        let partial = Invoice { days_worked: 2.5, overtime_days: 0.5, overtime_multiplier: 1.25, ..daily_rate };
+        assert_eq!(partial.billed_gross_profit(), 104_166);
+    }
+}
@@ -0,0 +1,2066 @@
+pub mod cents;
+pub mod config;
+mod document;
+pub mod error;
+pub mod expense;
+pub mod format;
+pub mod hook;
+pub mod invoice;
+#[cfg(feature = "live-rates")]
+pub mod live_rates;
+pub mod rates;
+mod report;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod storage;
+pub mod store;
+pub mod tax;
+
+use std::cell::{RefCell, RefMut};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use csv::WriterBuilder;
+use chrono::prelude::{Local, DateTime};
+use chrono::{Datelike, Duration, NaiveDate, TimeZone};
+use clap::ValueEnum;
+
+use cents::Cents;
+use config::{Config, PaymentTerms, Prepayments, TaxRules};
+use error::InvoiceError;
+use expense::{Deductions, Expense};
+use format::MoneyFormat;
+use rates::RateProvider;
+use storage::HEADERS;
+use store::InvoiceStore;
+
+pub use invoice::{Invoice, NewInvoice};
+pub use storage::{CsvFormat, SCHEMA_VERSION};
+pub use tax::TaxBucket;
+
+/// Currency the tax brackets and all stored amounts are in
+const BASE_CURRENCY: &str = "EUR";
+
+const NO_INVOICES: &str = "No invoices yet — add one with `new`";
+
+/// Gross profit of a year above which `breakeven-rate` gives up, a billion EUR
+const MAX_YEAR_GROSS_PROFIT: Cents = 100_000_000_000;
+
+/// The output of the commands borrowed as a file of its own, for `-`
+struct Output<'a>(RefMut<'a, Box<dyn Write>>);
+
+impl Write for Output<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Where the notes and warnings next to the output of a command go, e.g. stderr. Shared by the
+/// commands and the `on_write` hook of the store.
+pub type Notes = Rc<RefCell<dyn Write>>;
+
+/// Writes `message` as a line to `notes`. A note that can't be written is dropped, it never fails
+/// the command it is about.
+pub(crate) fn note(notes: &Notes, message: &str) {
+    let _ = writeln!(notes.borrow_mut(), "{}", message);
+}
+
+/// What the commands work on, passed to each of them: the store the invoices are kept in, the year
+/// whose invoices they cover and where they write their output
+pub struct Context {
+    pub store: Box<dyn InvoiceStore>,
+    /// Year selected with `--year`, the current one by default
+    pub year: i32,
+    /// Reports, listings and the `-` files of exports, e.g. stdout
+    out: RefCell<Box<dyn Write>>,
+    notes: Notes
+}
+
+impl Context {
+    pub fn new(store: Box<dyn InvoiceStore>, year: i32, out: Box<dyn Write>, notes: Notes) -> Context {
+        Context { store, year, out: RefCell::new(out), notes }
+    }
+
+    /// Where the commands write their output
+    pub fn out(&self) -> RefMut<'_, Box<dyn Write>> {
+        self.out.borrow_mut()
+    }
+
+    pub(crate) fn note(&self, message: &str) {
+        note(&self.notes, message);
+    }
+
+    /// Opens `path` for writing, `-` writes to the output of the commands
+    pub(crate) fn open_output(&self, path: &str) -> io::Result<Box<dyn Write + '_>> {
+        if path == "-" {
+            Ok(Box::new(Output(self.out())))
+        } else {
+            Ok(Box::new(File::create(path).map_err(|err| with_path(err, path))?))
+        }
+    }
+
+    /// Appends `invoice` to the file of the year it is dated in, so an invoice from another year,
+    /// e.g. an imported one, never ends up in this year's file.
+    pub(crate) fn write_invoice_to_csv(&self, invoice: Invoice) -> Result<(), InvoiceError> {
+        self.append_invoice(invoice.local_date().year(), invoice)
+    }
+
+    /// Appends `invoice` to the invoices of `year`, whatever year it is dated in
+    pub(crate) fn append_invoice(&self, year: i32, invoice: Invoice) -> Result<(), InvoiceError> {
+        invoice.check_invariants()?;
+
+        self.store.append(year, &invoice)
+    }
+
+    /// Years that have invoices, oldest first
+    pub(crate) fn invoice_years(&self) -> Result<Vec<i32>, InvoiceError> {
+        self.store.years()
+    }
+
+    /// Passes each invoice of `year` to `visit` as it is read, for totals over years too large to
+    /// hold in memory. Unlike `fetch_year_invoices` a missing or outdated file is left as it is, and
+    /// the rows that can't be read are left out with a warning.
+    pub(crate) fn for_each_year_invoice(&self, year: i32, mut visit: impl FnMut(Invoice)) -> Result<(), InvoiceError> {
+        self.store.for_each(year, &mut |invoice| match invoice {
+            Ok(invoice) => visit(invoice),
+            Err(err) => self.note(&format!("Warning: leaving out a row of {}", err)),
+        })
+    }
+
+    pub(crate) fn fetch_invoices(&self) -> Result<Vec<Invoice>, InvoiceError> {
+        self.fetch_year_invoices(self.year)
+    }
+
+    pub(crate) fn fetch_year_invoices(&self, year: i32) -> Result<Vec<Invoice>, InvoiceError> {
+        self.store.load(year)
+    }
+
+    pub(crate) fn fetch_year_expenses(&self, year: i32) -> Result<Vec<Expense>, InvoiceError> {
+        self.store.load_expenses(year)
+    }
+
+    /// Invoices of `year` taxed under the profile of `rules`, what the tax reports cover
+    pub(crate) fn fetch_profile_invoices(&self, year: i32, rules: &TaxRules) -> Result<Vec<Invoice>, InvoiceError> {
+        let mut invoices: Vec<Invoice> = self.fetch_year_invoices(year)?;
+        invoices.retain(|invoice| invoice.profile == rules.profile);
+
+        Ok(invoices)
+    }
+
+    /// Replaces all invoices of `year`
+    pub(crate) fn save_year_invoices(&self, year: i32, invoices: &[Invoice]) -> Result<(), InvoiceError> {
+        self.store.save(year, invoices)
+    }
+}
+
+impl Invoice {
+    /// Takes the invoices of `year` out of the store, so no command counts them anymore. The CSV
+    /// store moves the file into `archive/` under the invoice directory, moving it back restores it.
+    ///
+    /// The current year is still being invoiced in and only archived with `force`.
+    pub fn archive(context: &Context, year: i32, force: bool, quiet: bool) -> Result<(), InvoiceError> {
+        if !context.invoice_years()?.contains(&year) {
+            return Err(InvoiceError::Validation(format!("no invoices recorded for {}", year)));
+        }
+        if year == Local::now().year() && !force {
+            return Err(InvoiceError::Validation(format!(
+                "{} is the current year, pass `--force` to archive it anyway",
+                year
+            )));
+        }
+
+        let archived = context.store.archive(year)?;
+        if !quiet {
+            writeln!(context.out(), "{}", archived)?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the invoices of every year in `from` into the store in use, e.g. the CSV files into a
+    /// new database. Nothing is copied when the store already has invoices of one of the years.
+    pub fn migrate(context: &Context, from: &dyn InvoiceStore, quiet: bool) -> Result<(), InvoiceError> {
+        let existing = context.invoice_years()?;
+        let existing_expenses = context.store.expense_years()?;
+        // A year with only expenses has no invoice file, but its expenses are copied all the same
+        let mut years = from.years()?;
+        years.extend(from.expense_years()?);
+        years.sort();
+        years.dedup();
+        if let Some(year) = years.iter().find(|year| existing.contains(year) || existing_expenses.contains(year)) {
+            return Err(InvoiceError::Validation(format!(
+                "`{}` already has invoices or expenses of {}", context.store.location(), year
+            )));
+        }
+
+        for year in years {
+            let invoices = from.load(year)?;
+            if !invoices.is_empty() {
+                context.save_year_invoices(year, &invoices)?;
+            }
+            let expenses = from.load_expenses(year)?;
+            for expense in &expenses {
+                context.store.append_expense(year, expense)?;
+            }
+            if !quiet {
+                writeln!(
+                    context.out(),
+                    "Copied {} invoices and {} expenses of {} to {}",
+                    invoices.len(), expenses.len(), year, context.store.location()
+                )?;
+            }
+        }
+        if !quiet {
+            writeln!(
+                context.out(),
+                "The invoices in {} are left as they are, `export` writes CSV from now on", from.location()
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the totals of the year, folded over the files row by row since on the cash basis they
+    /// cover the files of all years.
+    pub fn accountant_info(
+        context: &Context,
+        basis: Basis,
+        hours_per_day: f64,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let mut totals = report::InvoiceTotals::default();
+
+        match basis {
+            Basis::Accrual => context.for_each_year_invoice(year, |invoice| totals.add(&invoice))?,
+            // Income counts in the year it was received, which may be after the year it was earned
+            Basis::Cash => {
+                for invoice_year in context.invoice_years()? {
+                    context.for_each_year_invoice(invoice_year, |invoice| {
+                        if invoice.paid_date.is_some_and(|paid_date| paid_date.year() == year) {
+                            totals.add(&invoice);
+                        }
+                    })?;
+                }
+            }
+        }
+
+        if totals.count == 0 {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+
+        writeln!(context.out(), "Total gross profit: {}", money.money(totals.gross_profit))?;
+        let expenses = context.fetch_year_expenses(year)?;
+        let deductible: Cents = expenses.iter().map(Expense::deductible).sum();
+        if deductible > 0 {
+            // An expense dated after the last invoice waits for the next one, the taxes were reckoned without it
+            let mut invoices = Vec::new();
+            context.for_each_year_invoice(year, |invoice| invoices.push(invoice))?;
+            let gross_profit: Cents = invoices.iter().map(|invoice| invoice.gross_profit).sum();
+            let deducted = gross_profit - expense::taxable_profits(&invoices, &expenses).iter().sum::<Cents>();
+
+            writeln!(context.out(), "Deductible expenses: {}", money.money(deductible))?;
+            writeln!(context.out(), "Expenses deducted: {}", money.money(deducted))?;
+            if deducted < deductible {
+                writeln!(
+                    context.out(),
+                    "Expenses not deducted yet: {}, they are deducted from income invoiced after them",
+                    money.money(deductible - deducted)
+                )?;
+            }
+            writeln!(context.out(), "Taxable profit: {}", money.money((totals.gross_profit - deducted).max(0)))?;
+        }
+        writeln!(context.out(), "Total net profit: {}", money.money(totals.net_profit))?;
+        writeln!(context.out(), "Total government tax: {}", money.money(totals.government_tax))?;
+        writeln!(context.out(), "Total social contribution: {}", money.money(totals.social_contribution_tax))?;
+        writeln!(context.out(), "Total taxes: {}", money.money(totals.total_tax))?;
+        writeln!(context.out(), "Effective tax rate: {:.2}%", totals.effective_tax_rate() * 100.0)?;
+        if totals.total_tax > 0 && totals.gross_profit > 0 {
+            let share = |tax: Cents, of: Cents| tax as f64 / of as f64 * 100.0;
+            writeln!(
+                context.out(),
+                "Income tax: {:.1}% of gross, {:.1}% of taxes",
+                share(totals.government_tax, totals.gross_profit), share(totals.government_tax, totals.total_tax)
+            )?;
+            writeln!(
+                context.out(),
+                "Social contribution: {:.1}% of gross, {:.1}% of taxes",
+                share(totals.social_contribution_tax, totals.gross_profit),
+                share(totals.social_contribution_tax, totals.total_tax)
+            )?;
+        }
+        writeln!(context.out(), "Invoices: {}", totals.count)?;
+        if totals.estimated_count > 0 {
+            writeln!(
+                context.out(),
+                "Estimated invoices: {}, gross {}",
+                totals.estimated_count, money.money(totals.estimated_gross_profit)
+            )?;
+        }
+        writeln!(
+            context.out(),
+            "Average gross per invoice: {}", money.money(totals.gross_profit / totals.count as Cents)
+        )?;
+        // Billing is in days, the hours are only derived from them to compare with hourly rates
+        let hours = totals.days_worked * hours_per_day;
+        writeln!(context.out(), "Hours worked: {} ({} a day)", hours, hours_per_day)?;
+        if hours > 0.0 {
+            writeln!(
+                context.out(),
+                "Net per hour: {}", money.money((totals.net_profit as f64 / hours).round() as Cents)
+            )?;
+        }
+        match totals.average_interval() {
+            Some(days) => writeln!(context.out(), "Average interval between invoices: {:.1} days", days)?,
+            None => writeln!(context.out(), "Average interval between invoices: needs at least two invoices")?,
+        }
+
+        // Totals per profile only say something once invoices are taxed under more than one
+        if totals.profiles.len() > 1 {
+            for (profile, (gross_profit, total_tax)) in &totals.profiles {
+                let profile = if profile.is_empty() { "default" } else { profile };
+                writeln!(
+                    context.out(),
+                    "Profile {}: gross {}, taxes {}",
+                    profile, money.money(*gross_profit), money.money(*total_tax)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prints the totals of the 365 days up to and including `at`, across the year files it spans.
+    /// The amounts are the stored ones, so each invoice counts with the taxes of its own year.
+    pub fn trailing_twelve_months(context: &Context, at: NaiveDate, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let mut trailing = report::TrailingTotals::new(at);
+        for year in trailing.years() {
+            context.for_each_year_invoice(year, |invoice| trailing.add(&invoice))?;
+        }
+        let (from, totals) = (trailing.from, trailing.totals);
+
+        if totals.count == 0 {
+            writeln!(context.out(), "No invoices from {} to {}", from, at)?;
+            return Ok(());
+        }
+
+        writeln!(context.out(), "From {} to {}:", from, at)?;
+        writeln!(context.out(), "Gross profit: {}", money.money(totals.gross_profit))?;
+        writeln!(context.out(), "Net profit: {}", money.money(totals.net_profit))?;
+        writeln!(context.out(), "Government tax: {}", money.money(totals.government_tax))?;
+        writeln!(context.out(), "Social contribution: {}", money.money(totals.social_contribution_tax))?;
+        writeln!(context.out(), "Taxes: {}", money.money(totals.total_tax))?;
+        writeln!(context.out(), "Effective tax rate: {:.2}%", totals.effective_tax_rate() * 100.0)?;
+        writeln!(context.out(), "Invoices: {}", totals.count)?;
+
+        Ok(())
+    }
+
+    /// Refuses to compute the taxes of `invoice` with the rules of another profile
+    fn check_profile(invoice: &Invoice, rules: &TaxRules) -> Result<(), InvoiceError> {
+        if invoice.profile == rules.profile {
+            return Ok(());
+        }
+
+        let hint = match invoice.profile.as_str() {
+            "" => "run without `--profile`".to_string(),
+            profile => format!("run with `--profile {}`", profile),
+        };
+        Err(InvoiceError::Validation(format!("invoice `{}` is taxed under another profile, {}", invoice.name, hint)))
+    }
+
+    pub fn explain(context: &Context, name: &str, rules: &TaxRules, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        let Some(position) = invoices.iter().position(|invoice| invoice.name == name) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", name)));
+        };
+        let invoice = &invoices[position];
+        Self::check_profile(invoice, rules)?;
+
+        // Taxes are progressive over the year, so the invoice is taxed on top of everything before it
+        let prior_gross_profit = tax::profile_gross_profit(&invoices[..position], &invoice.profile);
+        let expenses = context.fetch_year_expenses(context.year)?;
+        let mut deductions = Deductions::new(&expenses, &invoice.profile);
+        let prior_taxable_profit =
+            tax::profile_taxable_profit(&invoices[..position], &invoice.profile, &mut deductions);
+        let gross_profit = invoice.billed_gross_profit();
+        let deduction = deductions.take(invoice.local_date().date_naive(), gross_profit);
+        let taxable_profit = gross_profit - deduction;
+
+        if invoice.estimated {
+            writeln!(context.out(), "Estimated, the days are not final yet")?;
+        }
+        writeln!(context.out(), "Prior cumulative gross profit: {}", money.money(prior_gross_profit))?;
+        if prior_taxable_profit != prior_gross_profit {
+            writeln!(context.out(), "Prior cumulative taxable profit: {}", money.money(prior_taxable_profit))?;
+        }
+        writeln!(
+            context.out(),
+            "Gross profit: {} ({} days at {})",
+            money.money(gross_profit), invoice.days_worked, money.money_in(invoice.daily_rate, &invoice.currency)
+        )?;
+        if invoice.overtime_days > 0.0 {
+            writeln!(
+                context.out(),
+                "Overtime: {} days at {}x the daily rate", invoice.overtime_days, invoice.overtime_multiplier
+            )?;
+        }
+        if deduction > 0 {
+            writeln!(
+                context.out(),
+                "Expenses deducted: {}, {} taxable", money.money(deduction), money.money(taxable_profit)
+            )?;
+        }
+
+        let tax_buckets = tax::tax_buckets(&rules.brackets, invoice.local_date().date_naive());
+        let appliable_tax_buckets = tax::appliable_tax_buckets(&tax_buckets, prior_taxable_profit, taxable_profit);
+        for (bucket_gross_profit, tax) in &appliable_tax_buckets {
+            writeln!(
+                context.out(),
+                "Bracket {}%: {} taxed, government tax {}",
+                tax * 100.0,
+                money.money(*bucket_gross_profit),
+                money.money(cents::apply_rate(*bucket_gross_profit, *tax))
+            )?;
+        }
+
+        let (profit_after_government_tax, government_tax) = tax::calc_government_tax(appliable_tax_buckets);
+        let base = tax::social_contribution_base(rules.social_base, taxable_profit, profit_after_government_tax);
+        let (net_profit, social_contribution) =
+            tax::calc_social_contribution(profit_after_government_tax, base, rules.social_contribution_fee);
+
+        writeln!(context.out(), "Government tax: {}", money.money(government_tax))?;
+        writeln!(context.out(), "Profit after government tax: {}", money.money(profit_after_government_tax))?;
+        writeln!(context.out(), "Social contribution base: {}", money.money(base))?;
+        writeln!(context.out(), "Social contribution: {}", money.money(social_contribution))?;
+        writeln!(context.out(), "Net profit: {}", money.money(net_profit + deduction))?;
+
+        Ok(())
+    }
+
+    /// Prints what a prospective contract of `days_worked` at `daily_rate` would add on top of this
+    /// year's invoices. The brackets are progressive, so the net it adds is less than its gross at
+    /// the average rate of the year so far.
+    pub fn quote(
+        context: &Context,
+        days_worked: f64,
+        daily_rate: f64,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        if days_worked <= 0.0 || !days_worked.is_finite() {
+            return Err(InvoiceError::invalid("days", "needs to be more than 0"));
+        } else if daily_rate <= 0.0 || !daily_rate.is_finite() {
+            return Err(InvoiceError::invalid("rate", "needs to be more than 0"));
+        }
+
+        let year = context.year;
+        let invoices: Vec<Invoice> = context.fetch_profile_invoices(year, rules)?;
+        let prior_gross_profit = tax::profile_gross_profit(&invoices, &rules.profile);
+        let expenses = context.fetch_year_expenses(year)?;
+        let mut deductions = Deductions::new(&expenses, &rules.profile);
+        let prior_taxable_profit = tax::profile_taxable_profit(&invoices, &rules.profile, &mut deductions);
+        // Quoted as if invoiced today, or at the end of another year selected with `--year`
+        let date = Some(Local::now().date_naive())
+            .filter(|today| today.year() == year)
+            .unwrap_or_else(|| NaiveDate::from_ymd_opt(year, 12, 31).unwrap());
+        let tax_buckets = tax::tax_buckets(&rules.brackets, date);
+
+        let gross_profit = Self::gross_profit(days_worked, cents::from_amount(daily_rate));
+        let deduction = deductions.take(date, gross_profit);
+        let (gross_profit, net_profit, government_tax, social_contribution_tax) =
+            tax::calc_taxes(gross_profit, prior_taxable_profit, deduction, &tax_buckets, rules);
+        let total_tax = government_tax + social_contribution_tax;
+        let tax_rate = if gross_profit > 0 { total_tax as f64 / gross_profit as f64 } else { 0.0 };
+
+        writeln!(context.out(), "Income so far in {}: {}", year, money.money(prior_gross_profit))?;
+        if prior_taxable_profit != prior_gross_profit {
+            writeln!(
+                context.out(),
+                "Taxable so far in {}: {} after expenses", year, money.money(prior_taxable_profit)
+            )?;
+        }
+        writeln!(context.out(), "Incremental gross: {}", money.money(gross_profit))?;
+        if deduction > 0 {
+            writeln!(context.out(), "Expenses deducted from it: {}", money.money(deduction))?;
+        }
+        writeln!(context.out(), "Incremental government tax: {}", money.money(government_tax))?;
+        writeln!(context.out(), "Incremental social contribution: {}", money.money(social_contribution_tax))?;
+        writeln!(
+            context.out(),
+            "Incremental taxes: {} ({:.2}% of its gross)",
+            money.money(total_tax), tax_rate * 100.0
+        )?;
+        writeln!(context.out(), "Incremental net: {}", money.money(net_profit))?;
+        if !invoices.is_empty() {
+            let average_net = gross_profit - cents::apply_rate(gross_profit, tax::effective_tax_rate(&invoices));
+            writeln!(context.out(), "Net at the average tax rate of {} would be {}", year, money.money(average_net))?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the lowest daily rate at which `billable_days` days leave `annual_costs` after taxes, for a
+    /// year with no other income. The taxes are inverted by searching the gross, since the brackets
+    /// make the net piecewise and the minimum social contribution bends it at the low end.
+    pub fn breakeven_rate(
+        context: &Context,
+        annual_costs: f64,
+        billable_days: f64,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        if annual_costs <= 0.0 || !annual_costs.is_finite() {
+            return Err(InvoiceError::invalid("annual-costs", "needs to be more than 0"));
+        } else if billable_days <= 0.0 || billable_days > 366.0 || !billable_days.is_finite() {
+            return Err(InvoiceError::invalid("billable-days", "needs to be more than 0 and at most 366"));
+        }
+
+        let target = cents::from_amount(annual_costs);
+        let tax_buckets = tax::tax_buckets(&rules.brackets, Local::now().date_naive());
+        let year_taxes = |gross_profit: Cents| {
+            let (_, net_profit, government_tax, social_contribution) =
+                tax::calc_taxes(gross_profit, 0, 0, &tax_buckets, rules);
+            let top_up = (rules.minimum_social_contribution - social_contribution).max(0);
+            (net_profit - top_up, government_tax + social_contribution + top_up)
+        };
+
+        // Doubled until the net reaches the target, then narrowed down to the cent
+        let (mut low, mut high): (Cents, Cents) = (0, target);
+        while year_taxes(high).0 < target {
+            if high > MAX_YEAR_GROSS_PROFIT {
+                return Err(InvoiceError::Validation(format!(
+                    "no daily rate leaves {} after taxes over {} days, the taxes grow as fast as the gross",
+                    money.money(target), billable_days
+                )));
+            }
+            (low, high) = (high, high * 2);
+        }
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if year_taxes(middle).0 >= target {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        let daily_rate = (high as f64 / billable_days).ceil() as Cents;
+        let gross_profit = Self::gross_profit(billable_days, daily_rate);
+        let (net_profit, total_tax) = year_taxes(gross_profit);
+
+        writeln!(context.out(), "Break-even daily rate: {}", money.money(daily_rate))?;
+        writeln!(context.out(), "Gross over {} days: {}", billable_days, money.money(gross_profit))?;
+        writeln!(context.out(), "Taxes: {}", money.money(total_tax))?;
+        writeln!(context.out(), "Net: {}", money.money(net_profit))?;
+        writeln!(context.out(), "Estimated for a year without other income, under the brackets in effect today")?;
+
+        Ok(())
+    }
+
+    /// Prints the projected year-end totals if `days_per_week` days at `daily_rate` are invoiced
+    /// every remaining full week of the year. The weekly invoices are run through the same
+    /// recalculation as the recorded ones, minimum social contribution included, but not saved.
+    pub fn plan(
+        context: &Context,
+        days_per_week: f64,
+        daily_rate: f64,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        if days_per_week <= 0.0 || days_per_week > 7.0 || !days_per_week.is_finite() {
+            return Err(InvoiceError::invalid("days-per-week", "needs to be more than 0 and at most 7"));
+        } else if daily_rate <= 0.0 || !daily_rate.is_finite() {
+            return Err(InvoiceError::invalid("rate", "needs to be more than 0"));
+        }
+
+        let today = Local::now().date_naive();
+        let year_end = NaiveDate::from_ymd_opt(today.year(), 12, 31).unwrap();
+        let weeks = (year_end - today).num_days() / 7;
+
+        let invoices: Vec<Invoice> = context.fetch_profile_invoices(today.year(), rules)?;
+        let expenses = context.fetch_year_expenses(today.year())?;
+        let gross_profit_so_far: Cents = invoices.iter().map(|invoice| invoice.gross_profit).sum();
+
+        let mut projected = invoices;
+        for week in 1..=weeks {
+            let day = today + Duration::weeks(week);
+            let Some(noon) = Local.from_local_datetime(&day.and_hms_opt(12, 0, 0).unwrap()).earliest() else {
+                continue;
+            };
+
+            projected.push(Invoice {
+                name: format!("plan_{}", week),
+                date: noon.timestamp_millis() as u128,
+                days_worked: days_per_week,
+                daily_rate: cents::from_amount(daily_rate),
+                currency: BASE_CURRENCY.to_string(),
+                original_daily_rate: Some(cents::from_amount(daily_rate)),
+                client: String::new(),
+                client_vat_id: String::new(),
+                estimated: true,
+                paid_date: None,
+                profile: rules.profile.clone(),
+                overtime_days: 0.0,
+                overtime_multiplier: 1.0,
+                vat_rate: 0.0,
+                vat: 0,
+                reverse_charge: false,
+                gross_profit: 0,
+                net_profit: 0,
+                government_tax: 0,
+                social_contribution_tax: 0,
+                total_tax: 0
+            });
+        }
+        tax::recalculate_year(&mut projected, &expenses, rules);
+
+        let gross_profit: Cents = projected.iter().map(|invoice| invoice.gross_profit).sum();
+        let net_profit: Cents = projected.iter().map(|invoice| invoice.net_profit).sum();
+        let total_tax: Cents = projected.iter().map(|invoice| invoice.total_tax).sum();
+
+        writeln!(context.out(), "Remaining full weeks: {}", weeks)?;
+        writeln!(context.out(), "Gross so far this year: {}", money.money(gross_profit_so_far))?;
+        writeln!(context.out(), "Planned gross: {}", money.money(gross_profit - gross_profit_so_far))?;
+        writeln!(context.out(), "Projected year-end gross: {}", money.money(gross_profit))?;
+        writeln!(context.out(), "Projected year-end net: {}", money.money(net_profit))?;
+        writeln!(
+            context.out(),
+            "Projected year-end taxes: {} ({:.2}% effective)",
+            money.money(total_tax), tax::effective_tax_rate(&projected) * 100.0
+        )?;
+
+        Ok(())
+    }
+
+    /// Prints the totals of this year's invoices recalculated under the rules of two configs, `a` and
+    /// `b` naming them, and what changes going from the first to the second. Nothing is saved.
+    pub fn compare_rules(
+        context: &Context,
+        a: &str,
+        rules_a: &TaxRules,
+        b: &str,
+        rules_b: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+        let expenses = context.fetch_year_expenses(context.year)?;
+
+        let totals = |rules: &TaxRules| {
+            let mut recalculated: Vec<Invoice> = invoices
+                .iter()
+                .filter(|invoice| invoice.profile == rules.profile)
+                .cloned()
+                .collect();
+            tax::recalculate_year(&mut recalculated, &expenses, rules);
+
+            let net_profit: Cents = recalculated.iter().map(|invoice| invoice.net_profit).sum();
+            let total_tax: Cents = recalculated.iter().map(|invoice| invoice.total_tax).sum();
+            (net_profit, total_tax, tax::effective_tax_rate(&recalculated))
+        };
+        let (net_a, tax_a, rate_a) = totals(rules_a);
+        let (net_b, tax_b, rate_b) = totals(rules_b);
+
+        for (name, net_profit, total_tax, rate) in [(a, net_a, tax_a, rate_a), (b, net_b, tax_b, rate_b)] {
+            writeln!(
+                context.out(),
+                "{}: net {}, taxes {}, effective rate {:.2}%",
+                name, money.money(net_profit), money.money(total_tax), rate * 100.0
+            )?;
+        }
+        writeln!(
+            context.out(),
+            "Difference: net {}, taxes {}, effective rate {:+.2} points",
+            money.money(net_b - net_a), money.money(tax_b - tax_a), (rate_b - rate_a) * 100.0
+        )?;
+
+        Ok(())
+    }
+
+    /// Prints the total tax and effective rate of yearly incomes from `from` to `to`, as if the
+    /// whole income were a single invoice.
+    pub fn rate_table(
+        context: &Context,
+        from: f64,
+        to: f64,
+        step: f64,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        if !step.is_finite() {
+            return Err(InvoiceError::invalid("step", "needs to be a number"));
+        } else if from > to {
+            return Err(InvoiceError::Validation("`from` can not be more than `to`".to_string()));
+        }
+
+        let (from, to, step) = (cents::from_amount(from), cents::from_amount(to), cents::from_amount(step));
+        // Checked in cents, a step that rounds to nothing would never reach `to`
+        if step < 1 {
+            return Err(InvoiceError::invalid("step", "needs to be at least 1 cent"));
+        }
+        let tax_buckets = tax::tax_buckets(&rules.brackets, Local::now().date_naive());
+        let mut income = from;
+        while income <= to {
+            let (_, _, government_tax, social_contribution) =
+                tax::calc_taxes(income, 0, 0, &tax_buckets, rules);
+            let total_tax = government_tax + social_contribution;
+            let effective_rate = if income > 0 { total_tax as f64 / income as f64 } else { 0.0 };
+
+            writeln!(
+                context.out(),
+                "{}: taxes {}, effective rate {:.2}%",
+                money.money(income), money.money(total_tax), effective_rate * 100.0
+            )?;
+            income += step;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the invoice document to send to the client, with the address of the client from the
+    /// registry of the config. As HTML it fills in the `invoice_template` of the config, if any.
+    pub fn render(
+        context: &Context,
+        name: &str,
+        out: &str,
+        format: DocumentFormat,
+        config: &Config,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        let Some(invoice) = invoices.iter().find(|invoice| invoice.name == name) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", name)));
+        };
+        let due = config.payment_terms.due_date(invoice.local_date().date_naive(), &config.holidays);
+        let client = Some(invoice.client.as_str())
+            .filter(|client| !client.is_empty())
+            .and_then(|client| config.client(client));
+
+        match format {
+            DocumentFormat::Text => {
+                document::write_invoice_document(context.open_output(out)?, invoice, &config.issuer, client, due, money)
+            }
+            DocumentFormat::Html => {
+                let template = match &config.invoice_template {
+                    Some(path) => std::fs::read_to_string(path)
+                        .map_err(|err| with_path(err, &path.display().to_string()))?,
+                    None => document::HTML_TEMPLATE.to_string(),
+                };
+                let writer = context.open_output(out)?;
+                document::write_invoice_html(writer, invoice, &config.issuer, client, due, money, &template)
+            }
+        }
+    }
+
+    /// Splits an invoice whose work spans `at` into the part before it and the part from it on.
+    ///
+    /// The work is assumed to be the `days_worked` consecutive days up to the invoice date. Both
+    /// parts keep the daily rate and a share of the overtime in proportion to their days, and are
+    /// moved into the file of the year they fall in, after which the taxes of the affected years
+    /// are recalculated.
+    pub fn split(context: &Context, name: &str, at: NaiveDate, rules: &TaxRules) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+        let Some(position) = invoices.iter().position(|invoice| invoice.name == name) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", name)));
+        };
+
+        let invoice = &invoices[position];
+        Self::check_profile(invoice, rules)?;
+        let calendar_days = invoice.days_worked.ceil() as i64;
+        let first_day = invoice.local_date().date_naive() - Duration::days(calendar_days - 1);
+        let days_before = (at - first_day).num_days().clamp(0, calendar_days) as f64;
+
+        if days_before <= 0.0 || days_before >= invoice.days_worked {
+            return Err(InvoiceError::Validation(format!("invoice `{}` has no work on both sides of {}", name, at)));
+        }
+
+        let invoice = invoices.remove(position);
+        let Some(midnight) = Local.from_local_datetime(&at.and_hms_opt(0, 0, 0).unwrap()).earliest() else {
+            return Err(InvoiceError::Validation(format!("{} has no local midnight to split at", at)));
+        };
+        let before_date = midnight.timestamp_millis() as u128 - 1;
+        let before_year = at.pred_opt().unwrap().year();
+        let after_year = at.year();
+
+        let overtime_before = invoice.overtime_days * days_before / invoice.days_worked;
+
+        let before = Invoice {
+            name: format!("{}_1", invoice.name),
+            date: before_date,
+            days_worked: days_before,
+            overtime_days: overtime_before,
+            ..invoice.clone()
+        };
+        let after = Invoice {
+            name: format!("{}_2", invoice.name),
+            days_worked: invoice.days_worked - days_before,
+            overtime_days: invoice.overtime_days - overtime_before,
+            ..invoice
+        };
+
+        let mut years: Vec<(i32, Vec<Invoice>)> = vec![(year, invoices)];
+        for (part_year, part) in [(before_year, before), (after_year, after)] {
+            let position = match years.iter().position(|(existing, _)| *existing == part_year) {
+                Some(position) => position,
+                None => {
+                    years.push((part_year, context.fetch_year_invoices(part_year)?));
+                    years.len() - 1
+                }
+            };
+
+            if years[position].1.iter().any(|existing| existing.name == part.name) {
+                return Err(InvoiceError::Validation(format!(
+                    "an invoice named `{}` already exists in {}", part.name, part_year
+                )));
+            }
+            years[position].1.push(part);
+        }
+
+        for (year, mut invoices) in years {
+            tax::recalculate_year(&mut invoices, &context.fetch_year_expenses(year)?, rules);
+            context.save_year_invoices(year, &invoices)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records this month's invoice of every active recurring invoice that is due, skipping the
+    /// ones already recorded so running it twice in a month doesn't double them.
+    pub fn run_recurring(
+        context: &Context,
+        config: &Config,
+        rules: &TaxRules,
+        rates: &dyn RateProvider,
+        money: &MoneyFormat,
+        quiet: bool
+    ) -> Result<(), InvoiceError> {
+        let today = Local::now().date_naive();
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        for template in config.recurring.iter().filter(|template| template.active) {
+            let name = template.invoice_name(today.year(), today.month());
+
+            let due_day = template.due_day(today.year(), today.month());
+            if today.day() < due_day {
+                if !quiet {
+                    writeln!(context.out(), "`{}` is not due before day {}", name, due_day)?;
+                }
+            } else if invoices.iter().any(|invoice| invoice.name == name) {
+                if !quiet {
+                    writeln!(context.out(), "`{}` was already recorded", name)?;
+                }
+            } else {
+                let new = NewInvoice {
+                    daily_rate: template.daily_rate,
+                    currency: template.currency.clone(),
+                    client: template.client.clone(),
+                    client_vat_id: template.client_vat_id.clone(),
+                    reverse_charge: template.reverse_charge,
+                    // Every month repeats the same work, the name already tells the months apart
+                    force: true,
+                    ..NewInvoice::new(name.clone(), template.days_worked)
+                };
+                Self::new(context, new, config, rules, rates, money, quiet)?;
+                if !quiet {
+                    writeln!(context.out(), "Recorded `{}`", name)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes the taxes of this year's invoices and prints the ones that change, saving
+    /// the new figures unless `dry_run` is set. With `quiet` only a dry run prints anything.
+    pub fn recalculate(
+        context: &Context,
+        dry_run: bool,
+        quiet: bool,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let verbose = dry_run || !quiet;
+        let year = context.year;
+        let invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+        if invoices.is_empty() {
+            if verbose {
+                writeln!(context.out(), "{}", NO_INVOICES)?;
+            }
+            return Ok(());
+        }
+
+        let mut recalculated = invoices.clone();
+        tax::recalculate_year(&mut recalculated, &context.fetch_year_expenses(year)?, rules);
+
+        let mut changed = 0;
+        for new in &recalculated {
+            let Some(old) = invoices.iter().find(|invoice| invoice.name == new.name) else {
+                continue;
+            };
+            if old.gross_profit == new.gross_profit
+                && old.net_profit == new.net_profit
+                && old.total_tax == new.total_tax {
+                continue;
+            }
+
+            if verbose {
+                writeln!(
+                    context.out(),
+                    "{}: gross {} -> {}, net {} -> {}, taxes {} -> {}",
+                    new.name,
+                    money.money(old.gross_profit), money.money(new.gross_profit),
+                    money.money(old.net_profit), money.money(new.net_profit),
+                    money.money(old.total_tax), money.money(new.total_tax)
+                )?;
+            }
+            changed += 1;
+        }
+
+        if changed > 0 && !dry_run {
+            context.save_year_invoices(year, &recalculated)?;
+        }
+
+        if !verbose {
+            return Ok(());
+        }
+        if changed == 0 {
+            writeln!(context.out(), "All invoices are up to date")?;
+        } else if dry_run {
+            writeln!(context.out(), "{} invoices would change, run without `--dry-run` to save them", changed)?;
+        } else {
+            writeln!(context.out(), "Recalculated {} invoices", changed)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fills in derived amounts that are missing or zero, e.g. in files written before a column
+    /// existed, and leaves the amounts that are already there alone.
+    pub fn backfill(context: &Context, dry_run: bool, quiet: bool, rules: &TaxRules) -> Result<(), InvoiceError> {
+        let verbose = dry_run || !quiet;
+        let mut changed = 0;
+
+        for year in context.invoice_years()? {
+            let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+            let expenses = context.fetch_year_expenses(year)?;
+            let mut year_changed = false;
+            invoices.sort_by_key(|invoice| invoice.date);
+
+            for position in 0..invoices.len() {
+                let (previous, rest) = invoices.split_at_mut(position);
+                let invoice = &mut rest[0];
+
+                let filled = Self::backfill_invoice(invoice, previous, &expenses, rules);
+                if filled.is_empty() {
+                    continue;
+                }
+
+                if verbose {
+                    writeln!(context.out(), "{}: filled in {}", invoice.name, filled.join(", "))?;
+                }
+                year_changed = true;
+                changed += 1;
+            }
+
+            if year_changed && !dry_run {
+                context.save_year_invoices(year, &invoices)?;
+            }
+        }
+
+        if !verbose {
+            return Ok(());
+        }
+        if changed == 0 {
+            writeln!(context.out(), "No missing amounts found")?;
+        } else if dry_run {
+            writeln!(context.out(), "{} invoices would change, run without `--dry-run` to save them", changed)?;
+        } else {
+            writeln!(context.out(), "Backfilled {} invoices", changed)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fills in the zero amounts of `invoice` that can be derived, returns the names of the ones filled
+    fn backfill_invoice(
+        invoice: &mut Invoice,
+        previous: &[Invoice],
+        expenses: &[Expense],
+        rules: &TaxRules
+    ) -> Vec<&'static str> {
+        let mut filled = Vec::new();
+
+        if invoice.gross_profit == 0 {
+            invoice.gross_profit = invoice.billed_gross_profit();
+            if invoice.gross_profit != 0 {
+                filled.push("gross profit");
+            }
+        }
+
+        // Both taxes zero means they were never computed, a single zero can be a real amount. They
+        // are only computed with the rules of the profile the invoice is taxed under.
+        if invoice.government_tax == 0
+            && invoice.social_contribution_tax == 0
+            && invoice.gross_profit != 0
+            && invoice.profile == rules.profile {
+            let tax_buckets = tax::tax_buckets(&rules.brackets, invoice.local_date().date_naive());
+            let mut deductions = Deductions::new(expenses, &rules.profile);
+            let prior_taxable_profit = tax::profile_taxable_profit(previous, &rules.profile, &mut deductions);
+            let deduction = deductions.take(invoice.local_date().date_naive(), invoice.billed_gross_profit());
+            let (_, _, government_tax, social_contribution_tax) = tax::calc_taxes(
+                invoice.billed_gross_profit(),
+                prior_taxable_profit,
+                deduction,
+                &tax_buckets,
+                rules
+            );
+
+            invoice.government_tax = government_tax;
+            invoice.social_contribution_tax = social_contribution_tax;
+            filled.push("government tax");
+            filled.push("social contribution tax");
+        }
+
+        if invoice.total_tax == 0 {
+            invoice.total_tax = invoice.government_tax + invoice.social_contribution_tax;
+            if invoice.total_tax != 0 {
+                filled.push("total tax");
+            }
+        }
+
+        if invoice.net_profit == 0 {
+            invoice.net_profit = invoice.gross_profit - invoice.total_tax;
+            if invoice.net_profit != 0 {
+                filled.push("net profit");
+            }
+        }
+
+        filled
+    }
+
+    /// Lists the unpaid invoices of all years that are past their due date, most overdue first.
+    /// Estimates are left out, they are not final enough to have been sent.
+    pub fn overdue_report(
+        context: &Context,
+        terms: &PaymentTerms,
+        holidays: &[NaiveDate],
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let today = Local::now().date_naive();
+        let mut overdue: Vec<(Invoice, NaiveDate)> = Vec::new();
+
+        for year in context.invoice_years()? {
+            context.for_each_year_invoice(year, |invoice| {
+                if invoice.paid_date.is_some() || invoice.estimated {
+                    return;
+                }
+                let due = terms.due_date(invoice.local_date().date_naive(), holidays);
+                if due < today {
+                    overdue.push((invoice, due));
+                }
+            })?;
+        }
+
+        if overdue.is_empty() {
+            writeln!(context.out(), "No overdue invoices")?;
+            return Ok(());
+        }
+
+        overdue.sort_by_key(|(_, due)| *due);
+        for (invoice, due) in overdue {
+            writeln!(
+                context.out(),
+                "{}: due {}, {} days overdue, {}",
+                invoice.name, due, (today - due).num_days(), money.money(invoice.gross_profit)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the day the client paid an invoice, looking through the files of all years since an
+    /// invoice is often paid in the year after it was sent.
+    pub fn mark_paid(context: &Context, name: &str, on: NaiveDate) -> Result<(), InvoiceError> {
+        for year in context.invoice_years()?.into_iter().rev() {
+            let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+            if let Some(invoice) = invoices.iter_mut().find(|invoice| invoice.name == name) {
+                invoice.paid_date = Some(on);
+                return context.save_year_invoices(year, &invoices);
+            }
+        }
+
+        Err(InvoiceError::Validation(format!("no invoice named `{}`", name)))
+    }
+
+    /// Marks an estimated invoice as final.
+    pub fn confirm(context: &Context, name: &str) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+        let Some(invoice) = invoices.iter_mut().find(|invoice| invoice.name == name) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", name)));
+        };
+        if !invoice.estimated {
+            return Err(InvoiceError::Validation(format!("invoice `{}` is already confirmed", name)));
+        }
+        invoice.estimated = false;
+
+        context.save_year_invoices(year, &invoices)
+    }
+
+    /// Renames an invoice. The name plays no part in the tax computation, so none of the
+    /// stored figures change.
+    pub fn rename(context: &Context, old: &str, new: &str) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+        if invoices.iter().any(|invoice| invoice.name == new) {
+            return Err(InvoiceError::DuplicateName(new.to_string()));
+        }
+        let Some(invoice) = invoices.iter_mut().find(|invoice| invoice.name == old) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", old)));
+        };
+        invoice.name = new.to_string();
+
+        context.save_year_invoices(year, &invoices)
+    }
+
+    /// Corrects the days, rate or client of an invoice, the rate in the currency of the invoice.
+    ///
+    /// The invoices after it are taxed on top of it, so the taxes of the year are recalculated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        context: &Context,
+        name: &str,
+        days_worked: Option<f64>,
+        daily_rate: Option<f64>,
+        client: Option<String>,
+        config: &Config,
+        rules: &TaxRules,
+        rates: &dyn RateProvider
+    ) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+        let Some(invoice) = invoices.iter_mut().find(|invoice| invoice.name == name) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", name)));
+        };
+        Self::check_profile(invoice, rules)?;
+
+        if let Some(days_worked) = days_worked {
+            if days_worked <= 0.0 || !days_worked.is_finite() {
+                return Err(InvoiceError::invalid("days_worked", "needs to be more than 0"));
+            }
+            if let Some(increment) = config.billing_increment {
+                if !Self::is_billing_multiple(days_worked, increment) {
+                    return Err(InvoiceError::invalid(
+                        "days_worked", format!("needs to be a multiple of the billing increment {}", increment)
+                    ));
+                }
+            }
+            invoice.days_worked = days_worked;
+        }
+        if let Some(daily_rate) = daily_rate {
+            if daily_rate == 0.0 && !config.allow_zero_rate {
+                return Err(InvoiceError::invalid(
+                    "daily_rate", "can not be 0.0, set `allow_zero_rate` in the config for pro-bono work"
+                ));
+            }
+            invoice.original_daily_rate = Some(cents::from_amount(daily_rate));
+            invoice.daily_rate = cents::from_amount(rates::convert_to_eur(rates, daily_rate, &invoice.currency)?);
+        }
+        if let Some(client) = client {
+            invoice.client = client;
+        }
+
+        tax::recalculate_year(&mut invoices, &context.fetch_year_expenses(year)?, rules);
+        context.save_year_invoices(year, &invoices)
+    }
+
+    /// Deletes an invoice, after which the taxes of the year are recalculated without it.
+    pub fn delete(context: &Context, name: &str, rules: &TaxRules) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+        let Some(position) = invoices.iter().position(|invoice| invoice.name == name) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", name)));
+        };
+        Self::check_profile(&invoices[position], rules)?;
+        invoices.remove(position);
+
+        tax::recalculate_year(&mut invoices, &context.fetch_year_expenses(year)?, rules);
+        context.save_year_invoices(year, &invoices)
+    }
+
+    /// Writes this year's invoices as CSV, after a `#` comment banner `import` skips when the config
+    /// asks for one. With `anonymize` the names, clients and VAT numbers are replaced by placeholders,
+    /// and `mapping` gets a CSV of which placeholder stands for what.
+    #[allow(clippy::too_many_arguments)]
+    pub fn export(
+        context: &Context,
+        out: &str,
+        format: CsvFormat,
+        header: bool,
+        anonymize: bool,
+        mapping: Option<&str>,
+        config: &Config,
+        rules: &TaxRules
+    ) -> Result<(), InvoiceError> {
+        let mut invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        if anonymize {
+            let replaced = Self::anonymize(&mut invoices);
+
+            if let Some(mapping) = mapping {
+                let mut writer = WriterBuilder::new().from_writer(context.open_output(mapping)?);
+                writer.write_record(["column", "placeholder", "original"])?;
+                for (column, placeholder, original) in replaced {
+                    writer.write_record([column, &placeholder, &original])?;
+                }
+                writer.flush()?;
+            }
+        }
+
+        let mut writer = context.open_output(out)?;
+        // Rows without a header go below an existing one, where a banner would be in the way
+        if config.export_banner && format.comments && header {
+            Self::write_export_banner(&mut writer, config, rules)?;
+        }
+
+        storage::write_invoices_as(writer, &invoices, format, header)
+    }
+
+    /// `#` comment lines saying whose invoices an export holds, when and under which profile it was made
+    fn write_export_banner<W: Write>(writer: &mut W, config: &Config, rules: &TaxRules) -> Result<(), InvoiceError> {
+        let issuer = config.issuer.name.as_deref().unwrap_or("unnamed issuer");
+        let profile = if rules.profile.is_empty() { "default" } else { &rules.profile };
+        writeln!(writer, "# Invoices of {}", issuer)?;
+        writeln!(
+            writer,
+            "# Generated {} by accountant_tool {}", Local::now().to_rfc3339(), env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(writer, "# Tax profile {}", profile)?;
+
+        Ok(())
+    }
+
+    /// Replaces the identifying fields by placeholders, the same value always by the same one.
+    /// Returns the column, placeholder and original value of every replacement.
+    fn anonymize(invoices: &mut [Invoice]) -> Vec<(&'static str, String, String)> {
+        let mut replaced: Vec<(&'static str, String, String)> = Vec::new();
+
+        let mut placeholder = |column: &'static str, original: &str, name: fn(usize) -> String| -> String {
+            if original.is_empty() {
+                return String::new();
+            }
+            if let Some((_, placeholder, _)) = replaced
+                .iter()
+                .find(|(existing, _, value)| *existing == column && value == original) {
+                return placeholder.clone();
+            }
+
+            let count = replaced.iter().filter(|(existing, _, _)| *existing == column).count();
+            let placeholder = name(count);
+            replaced.push((column, placeholder.clone(), original.to_string()));
+            placeholder
+        };
+
+        for invoice in invoices.iter_mut() {
+            invoice.name = placeholder("name", &invoice.name, |index| format!("invoice_{}", index + 1));
+            invoice.client =
+                placeholder("client", &invoice.client, |index| format!("client_{}", Self::letters(index)));
+            invoice.client_vat_id =
+                placeholder("client_vat_id", &invoice.client_vat_id, |index| format!("vat_id_{}", index + 1));
+        }
+
+        replaced
+    }
+
+    /// Spreadsheet style column letters, `A` to `Z` and then `AA`, `AB` and so on
+    fn letters(index: usize) -> String {
+        let mut letters = Vec::new();
+        let mut rest = index + 1;
+        while rest > 0 {
+            rest -= 1;
+            letters.push(char::from(b'A' + (rest % 26) as u8));
+            rest /= 26;
+        }
+
+        letters.into_iter().rev().collect()
+    }
+
+    /// Adds the invoices of a CSV export, each to the file of the year it is dated in
+    pub fn import(context: &Context, input: &str, format: CsvFormat, quiet: bool) -> Result<(), InvoiceError> {
+        let imported: Vec<Invoice> = storage::invoice_rows(open_input(input)?, format)?.collect::<Result<_, _>>()?;
+        // Names of the invoices in each year's file, read once per year and kept up to date below
+        let mut names: HashMap<i32, Vec<String>> = HashMap::new();
+
+        let mut count = 0;
+        for invoice in imported {
+            let year = invoice.local_date().year();
+            let year_names = match names.entry(year) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(context.fetch_year_invoices(year)?.into_iter().map(|existing| existing.name).collect())
+                }
+            };
+
+            if year_names.contains(&invoice.name) {
+                context.note(&format!(
+                    "Skipping `{}`, an invoice with that name already exists in {}", invoice.name, year
+                ));
+                continue;
+            }
+            year_names.push(invoice.name.clone());
+            context.write_invoice_to_csv(invoice)?;
+            count += 1;
+        }
+
+        if !quiet {
+            context.note(&format!("Imported {} invoices", count));
+        }
+
+        Ok(())
+    }
+
+    /// Merges the invoices of another invoice file, e.g. one left behind in an old data directory,
+    /// into the files of the years they are dated in and recalculates those years.
+    ///
+    /// An invoice whose name is already recorded is skipped. When what was recorded for it differs,
+    /// the differing columns are reported; the computed amounts are left out since the years are
+    /// recalculated anyway.
+    pub fn merge(context: &Context, input: &str, rules: &TaxRules, quiet: bool) -> Result<(), InvoiceError> {
+        let (other, errors) = storage::read_invoices(open_input(input)?)?;
+        for err in &errors {
+            context.note(&format!("Warning: leaving out a row of {}, {}", input, err));
+        }
+        let mut years: Vec<(i32, Vec<Invoice>)> = Vec::new();
+
+        let (mut merged, mut conflicts) = (0, 0);
+        for invoice in other {
+            let year = invoice.local_date().year();
+            let position = match years.iter().position(|(existing, _)| *existing == year) {
+                Some(position) => position,
+                None => {
+                    years.push((year, context.fetch_year_invoices(year)?));
+                    years.len() - 1
+                }
+            };
+            let invoices = &mut years[position].1;
+
+            let Some(existing) = invoices.iter().find(|existing| existing.name == invoice.name) else {
+                invoices.push(invoice);
+                merged += 1;
+                continue;
+            };
+
+            let (existing, record) = (storage::to_record(existing), storage::to_record(&invoice));
+            let differing: Vec<&str> = (0..HEADERS.len())
+                .filter(|index| !(5..=9).contains(index) && existing[*index] != record[*index])
+                .map(|index| HEADERS[index])
+                .collect();
+            if !differing.is_empty() {
+                context.note(&format!(
+                    "Conflict: `{}` in {} differs in {}, keeping the recorded one",
+                    invoice.name, year, differing.join(", ")
+                ));
+                conflicts += 1;
+            }
+        }
+
+        for (year, mut invoices) in years {
+            tax::recalculate_year(&mut invoices, &context.fetch_year_expenses(year)?, rules);
+            context.save_year_invoices(year, &invoices)?;
+        }
+
+        if !quiet {
+            context.note(&format!("Merged {} invoices, {} conflicts", merged, conflicts));
+        }
+
+        Ok(())
+    }
+
+    pub fn monthly_summary(
+        context: &Context,
+        out: Option<&str>,
+        exclude_estimated: bool,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let invoices: Vec<Invoice> = context.fetch_profile_invoices(year, rules)?;
+        let expenses = context.fetch_year_expenses(year)?;
+        let months = report::monthly_summary(year, &invoices, &expenses, rules, exclude_estimated);
+
+        if let Some(out) = out {
+            return report::write_monthly_summary(context.open_output(out)?, &months);
+        }
+
+        if invoices.is_empty() {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+
+        for month in months {
+            let estimated = if month.estimated_gross_profit > 0 {
+                format!(" ({} estimated)", money.money(month.estimated_gross_profit))
+            } else {
+                String::new()
+            };
+
+            writeln!(
+                context.out(),
+                "{:02}: gross {}{}, net {}, taxes {}, recommended reserve {}",
+                month.month,
+                money.money(month.gross_profit),
+                estimated,
+                money.money(month.net_profit),
+                money.money(month.total_tax),
+                money.money(month.tax_reserve)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn bracket_report(context: &Context, rules: &TaxRules, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let invoices: Vec<Invoice> = context.fetch_profile_invoices(year, rules)?;
+
+        if invoices.is_empty() {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+
+        let expenses = context.fetch_year_expenses(year)?;
+        for total in report::bracket_totals(year, &invoices, &expenses, &rules.brackets) {
+            writeln!(
+                context.out(),
+                "Bracket {}%: {} taxed, government tax {}",
+                total.perc * 100.0, money.money(total.gross_profit), money.money(total.government_tax)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn accountant_export(
+        context: &Context,
+        out: &str,
+        format: ReportFormat,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let invoices: Vec<Invoice> = context.fetch_profile_invoices(year, rules)?;
+        let report = report::accountant_report(year, &invoices, &context.fetch_year_expenses(year)?, rules);
+
+        let mut writer = context.open_output(out)?;
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, &report)?;
+                writeln!(writer)?;
+                Ok(())
+            }
+            ReportFormat::Text => report::write_accountant_report_text(writer, &report, money),
+        }
+    }
+
+    /// Prints one line per invoice of the year, oldest first, with the names and clients in aligned columns
+    pub fn list(context: &Context, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let mut invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        if invoices.is_empty() {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+        invoices.sort_by_key(|invoice| invoice.date);
+
+        let width = |column: fn(&Invoice) -> &str| {
+            invoices.iter().map(|invoice| format::display_width(column(invoice))).max().unwrap_or(0)
+        };
+        let name_width = width(|invoice| &invoice.name);
+        let client_width = width(|invoice| &invoice.client);
+        for invoice in &invoices {
+            let status = match (invoice.estimated, invoice.paid_date) {
+                (true, _) => "estimate".to_string(),
+                (false, Some(paid_date)) => format!("paid {}", paid_date),
+                (false, None) => "unpaid".to_string(),
+            };
+            writeln!(
+                context.out(),
+                "{}  {}  {}  {} days  gross {}  net {}  {}",
+                format::pad_end(&invoice.name, name_width),
+                invoice.local_date().format("%Y-%m-%d"),
+                format::pad_end(&invoice.client, client_width),
+                invoice.days_worked,
+                money.money(invoice.gross_profit),
+                money.money(invoice.net_profit),
+                status
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Records an expense paid on `date`, of which `deductible_share` is deducted from the taxable
+    /// profit of the invoices dated on or after it. The invoices of its year are recalculated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_expense(
+        context: &Context,
+        name: &str,
+        amount: f64,
+        date: NaiveDate,
+        deductible_share: f64,
+        rules: &TaxRules,
+        money: &MoneyFormat,
+        quiet: bool
+    ) -> Result<(), InvoiceError> {
+        if name.trim().is_empty() {
+            return Err(InvoiceError::invalid("name", "can not be empty"));
+        } else if amount <= 0.0 || !amount.is_finite() {
+            return Err(InvoiceError::invalid("amount", "needs to be more than 0"));
+        } else if deductible_share <= 0.0 || deductible_share > 1.0 || !deductible_share.is_finite() {
+            return Err(InvoiceError::invalid("deductible-share", "needs to be more than 0 and at most 1"));
+        }
+
+        let year = date.year();
+        let expense = Expense {
+            name: name.to_string(),
+            date,
+            amount: cents::from_amount(amount),
+            deductible_share,
+            profile: rules.profile.clone()
+        };
+        context.store.append_expense(year, &expense)?;
+
+        let mut invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+        if !invoices.is_empty() {
+            tax::recalculate_year(&mut invoices, &context.fetch_year_expenses(year)?, rules);
+            context.save_year_invoices(year, &invoices)?;
+        }
+        if !quiet {
+            writeln!(context.out(), "Recorded {}, {} deductible", expense.name, money.money(expense.deductible()))?;
+            if !invoices.is_empty() {
+                context.note(&format!("Recalculated the {} invoices of {}", invoices.len(), year));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the expenses of the year with their deductible part, and what they deduct in total
+    pub fn list_expenses(context: &Context, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let mut expenses: Vec<Expense> = context.fetch_year_expenses(context.year)?;
+
+        if expenses.is_empty() {
+            writeln!(context.out(), "No expenses yet — add one with `expense add`")?;
+            return Ok(());
+        }
+        expenses.sort_by_key(|expense| expense.date);
+
+        let name_width = expenses.iter().map(|expense| format::display_width(&expense.name)).max().unwrap_or(0);
+        for expense in &expenses {
+            writeln!(
+                context.out(),
+                "{}  {}  {}  deductible {}",
+                format::pad_end(&expense.name, name_width),
+                expense.date,
+                money.money(expense.amount),
+                money.money(expense.deductible())
+            )?;
+        }
+        let deductible: Cents = expenses.iter().map(Expense::deductible).sum();
+        writeln!(context.out(), "Deductible in total: {}", money.money(deductible))?;
+
+        Ok(())
+    }
+
+    /// Prints every field of the invoice named `name`, as text or as a JSON object
+    pub fn show(context: &Context, name: &str, json: bool, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        let Some(invoice) = invoices.iter().find(|invoice| invoice.name == name) else {
+            return Err(InvoiceError::Validation(format!("no invoice named `{}`", name)));
+        };
+        let record = report::invoice_record(invoice);
+
+        let mut out = context.out();
+        if json {
+            serde_json::to_writer_pretty(&mut *out, &record)?;
+            writeln!(out)?;
+            return Ok(());
+        }
+        report::write_invoice_record_text(&mut *out, &record, money)
+    }
+
+    pub fn gaps_report(context: &Context, min_days: Option<i64>) -> Result<(), InvoiceError> {
+        let min_days = min_days.unwrap_or(Self::GAP_DAYS);
+        if min_days < 0 {
+            return Err(InvoiceError::invalid("min-days", "can not be negative"));
+        }
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        if invoices.is_empty() {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+
+        // The selected year, up to today while it is still running
+        let start = NaiveDate::from_ymd_opt(context.year, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(context.year, 12, 31).unwrap().min(Local::now().date_naive());
+        let dates: Vec<NaiveDate> = invoices.iter().map(|invoice| invoice.local_date().date_naive()).collect();
+
+        let gaps = report::invoice_gaps(&dates, start, end, min_days);
+        if gaps.is_empty() {
+            writeln!(context.out(), "No stretches without invoices")?;
+        }
+        for (from, to) in gaps {
+            writeln!(context.out(), "{} to {}: {} days without invoices", from, to, (to - from).num_days() + 1)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn top_clients_report(context: &Context, limit: usize, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        if invoices.is_empty() {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+
+        let clients: Vec<_> = report::top_clients(&invoices).into_iter().take(limit).collect();
+        // Names are padded by display width, so wide and accented ones keep the columns aligned
+        let width = clients.iter().map(|client| format::display_width(&client.client) + 1).max().unwrap_or(0);
+        for client in &clients {
+            writeln!(
+                context.out(),
+                "{} gross {}, net {}, {:.1}% of revenue",
+                format::pad_end(&format!("{}:", client.client), width),
+                money.money(client.gross_profit),
+                money.money(client.net_profit),
+                client.share * 100.0
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the quartiles and a histogram of the gross profit of this year's invoices
+    pub fn stats_report(context: &Context, bins: usize, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let invoices: Vec<Invoice> = context.fetch_invoices()?;
+
+        let Some(distribution) = report::gross_distribution(&invoices, bins)? else {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        };
+
+        writeln!(context.out(), "Invoices: {}", invoices.len())?;
+        writeln!(context.out(), "Smallest: {}", money.money(distribution.min))?;
+        writeln!(context.out(), "First quartile: {}", money.money(distribution.first_quartile))?;
+        writeln!(context.out(), "Median: {}", money.money(distribution.median))?;
+        writeln!(context.out(), "Third quartile: {}", money.money(distribution.third_quartile))?;
+        writeln!(context.out(), "Largest: {}", money.money(distribution.max))?;
+
+        writeln!(context.out())?;
+        let labels: Vec<String> = distribution.histogram.iter().map(|(from, _)| money.money(*from)).collect();
+        let width = labels.iter().map(|label| format::display_width(label)).max().unwrap_or(0);
+        for (label, (_, count)) in labels.iter().zip(&distribution.histogram) {
+            writeln!(context.out(), "{} {} {}", format::pad_start(label, width), "#".repeat(*count), count)?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the turnover and VAT of each quarter, or of `quarter` only, as the VAT return asks for them.
+    ///
+    /// VAT is owed on every invoice sent, whatever its tax profile, but not on estimates.
+    pub fn vat_report(context: &Context, quarter: Option<Quarter>, money: &MoneyFormat) -> Result<(), InvoiceError> {
+        let year = context.year;
+        let invoices: Vec<Invoice> = context.fetch_year_invoices(year)?;
+
+        if invoices.is_empty() {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+
+        let quarters = report::vat_quarters(&invoices);
+        let selected = quarters
+            .iter()
+            .filter(|vat_quarter| quarter.is_none_or(|quarter| vat_quarter.quarter == quarter.number()));
+        for vat_quarter in selected {
+            writeln!(context.out(), "VAT {} Q{}", year, vat_quarter.quarter)?;
+            for rate in &vat_quarter.rates {
+                writeln!(
+                    context.out(),
+                    "  Turnover at {}%: {}, VAT {}",
+                    storage::format_decimal(rate.rate * 100.0), money.money(rate.turnover), money.money(rate.vat)
+                )?;
+            }
+            if vat_quarter.reverse_charged > 0 {
+                writeln!(context.out(), "  Turnover reverse charged: {}", money.money(vat_quarter.reverse_charged))?;
+            }
+            writeln!(context.out(), "  VAT collected: {}", money.money(vat_quarter.vat()))?;
+            if vat_quarter.estimated_count > 0 {
+                writeln!(
+                    context.out(),
+                    "  {} estimated invoices left out, confirm them once final", vat_quarter.estimated_count
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Estimates the interest owed on the tax of `year` that wasn't prepaid by each quarter's due date.
+    ///
+    /// `paid` replaces the prepayments configured for the year, in EUR one per quarter.
+    pub fn prepayment_check(
+        context: &Context,
+        year: i32,
+        paid: Option<&[f64]>,
+        prepayments: &Prepayments,
+        rules: &TaxRules,
+        money: &MoneyFormat
+    ) -> Result<(), InvoiceError> {
+        let paid = paid.map_or_else(|| prepayments.paid(year), <[f64]>::to_vec);
+        if paid.len() > 4 || paid.iter().any(|amount| *amount < 0.0 || !amount.is_finite()) {
+            return Err(InvoiceError::Validation("need at most four prepayments, none negative".to_string()));
+        }
+        let paid: Vec<Cents> = paid.into_iter().map(cents::from_amount).collect();
+        let due = prepayments.due_dates(year)?;
+
+        let invoices: Vec<Invoice> = context.fetch_profile_invoices(year, rules)?;
+        let today = Local::now().date_naive();
+        let quarters = report::quarterly_summary(&invoices);
+        let checked = report::prepayment_check(&quarters, &paid, &due, prepayments.penalty_rate, today);
+
+        writeln!(context.out(), "Prepayment check {} (estimate)", year)?;
+        writeln!(context.out())?;
+        for quarter in &checked {
+            let left = if quarter.due > today {
+                "not due yet".to_string()
+            } else if quarter.shortfall == 0 {
+                "nothing short".to_string()
+            } else {
+                format!(
+                    "{} short for {} days, interest {}",
+                    money.money(quarter.shortfall), quarter.days, money.money(quarter.interest)
+                )
+            };
+            writeln!(
+                context.out(),
+                "Q{} due {}: tax {}, prepaid {}, {}",
+                quarter.quarter, quarter.due, money.money(quarter.tax_due), money.money(quarter.paid), left
+            )?;
+        }
+
+        writeln!(context.out())?;
+        writeln!(
+            context.out(),
+            "Estimated interest: {}", money.money(checked.iter().map(|quarter| quarter.interest).sum())
+        )?;
+        writeln!(
+            context.out(),
+            "Estimated at {}% a year on what was short, the tax authority's own computation may differ.",
+            prepayments.penalty_rate * 100.0
+        )?;
+
+        Ok(())
+    }
+
+    /// Prints what a bug report needs to know about the setup: the version and features of the
+    /// binary, the file layout it writes, the tax profile and config in use and the years stored.
+    ///
+    /// Nothing is written, a file on an older layout is only reported.
+    pub fn version_report(
+        context: &Context,
+        rules: &TaxRules,
+        config_path: Option<&std::path::Path>
+    ) -> Result<(), InvoiceError> {
+        writeln!(context.out(), "accountant_tool {}", env!("CARGO_PKG_VERSION"))?;
+        let features: Vec<&str> = [("live-rates", cfg!(feature = "live-rates")), ("sqlite", cfg!(feature = "sqlite"))]
+            .into_iter()
+            .filter_map(|(feature, enabled)| enabled.then_some(feature))
+            .collect();
+        writeln!(
+            context.out(),
+            "Features: {}", if features.is_empty() { "none".to_string() } else { features.join(", ") }
+        )?;
+        writeln!(context.out(), "Data schema version: {}", SCHEMA_VERSION)?;
+        writeln!(context.out(), "Tax profile: {}", if rules.profile.is_empty() { "default" } else { &rules.profile })?;
+        match config_path {
+            Some(path) if path.exists() => writeln!(context.out(), "Config file: {}", path.display())?,
+            Some(path) => writeln!(context.out(), "Config file: {} (not created yet)", path.display())?,
+            None => writeln!(context.out(), "Config file: none, no config directory found")?,
+        }
+
+        writeln!(context.out(), "Invoices stored in: {}", context.store.location())?;
+        let years = context.invoice_years()?;
+        if years.is_empty() {
+            writeln!(context.out(), "Years: none")?;
+        }
+        for year in years {
+            writeln!(context.out(), "{}", context.store.describe(year)?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn break_even_report(
+        context: &Context,
+        monthly_expenses: f64,
+        reserve_ratio: Option<f64>,
+        rules: &TaxRules
+    ) -> Result<(), InvoiceError> {
+        let invoices: Vec<Invoice> = context.fetch_profile_invoices(Local::now().year(), rules)?;
+
+        if invoices.is_empty() {
+            writeln!(context.out(), "{}", NO_INVOICES)?;
+            return Ok(());
+        }
+
+        // Without an explicit ratio, reserve what has effectively been owed so far
+        let reserve_ratio = reserve_ratio.unwrap_or(tax::effective_tax_rate(&invoices));
+
+        let today = Local::now().date_naive();
+        let expenses = context.fetch_year_expenses(today.year())?;
+        let months = report::monthly_summary(today.year(), &invoices, &expenses, rules, false);
+
+        let monthly_expenses = cents::from_amount(monthly_expenses);
+        match report::break_even_date(today.year(), today, &months, reserve_ratio, monthly_expenses)? {
+            Some(date) => writeln!(context.out(), "Living costs for the year are covered on {}", date)?,
+            None => writeln!(context.out(), "Living costs for the year are not covered at the current run rate")?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes how much of each currency one EUR buys and where the rates come from
+pub fn write_currencies<W: Write>(out: &mut W, rates: &dyn RateProvider) -> io::Result<()> {
+    for (currency, rate) in rates.eur_rates() {
+        writeln!(out, "{}: {} {}", currency, rate, BASE_CURRENCY)?;
+    }
+
+    match rates.updated_at().and_then(|updated_at| DateTime::from_timestamp(updated_at as i64, 0)) {
+        Some(updated_at) => {
+            writeln!(out, "Rates fetched at {}", updated_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"))
+        }
+        None => writeln!(out, "Rates from the static table and config"),
+    }
+}
+
+/// Prefixes an I/O error with the path it happened on
+pub fn with_path(err: io::Error, path: &str) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path, err))
+}
+
+/// Opens `path` for reading, `-` reads from stdin
+fn open_input(path: &str) -> io::Result<Box<dyn Read>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(File::open(path).map_err(|err| with_path(err, path))?))
+    }
+}
+
+
+/// When income counts for taxes
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Basis {
+    /// When the invoice was sent
+    Accrual,
+    /// When the invoice was paid
+    Cash
+}
+
+/// Layout of a rendered invoice
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DocumentFormat {
+    /// Plain text, e.g. to paste in an email
+    Text,
+    /// An HTML page, to print to PDF from a browser
+    Html
+}
+
+/// Quarter of the year
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Quarter {
+    Q1,
+    Q2,
+    Q3,
+    Q4
+}
+
+impl Quarter {
+    /// 1 for `Q1` up to 4 for `Q4`
+    pub fn number(self) -> u32 {
+        self as u32 + 1
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Mutex, PoisonError};
+
+    use super::*;
+    use crate::hook::OnWrite;
+    use crate::storage::CsvStore;
+
+    /// Invoice of the default profile dated at noon of `date`
+    pub(crate) fn invoice(date: NaiveDate, gross_profit: Cents) -> Invoice {
+        let noon = Local.from_local_datetime(&date.and_hms_opt(12, 0, 0).unwrap()).unwrap();
+        Invoice {
+            name: date.to_string(),
+            date: noon.timestamp_millis() as u128,
+            days_worked: 1.0,
+            daily_rate: gross_profit,
+            currency: "EUR".to_string(),
+            original_daily_rate: None,
+            client: String::new(),
+            client_vat_id: String::new(),
+            estimated: false,
+            paid_date: None,
+            profile: String::new(),
+            overtime_days: 0.0,
+            overtime_multiplier: 1.0,
+            vat_rate: 0.0,
+            vat: 0,
+            reverse_charge: false,
+            gross_profit,
+            net_profit: 0,
+            government_tax: 0,
+            social_contribution_tax: 0,
+            total_tax: 0
+        }
+    }
+
+    pub(crate) fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Store of the files under `invoices` of the working directory, see `in_temp_dir`
+    pub(crate) fn csv_store() -> CsvStore {
+        CsvStore::new(PathBuf::from("invoices"), OnWrite::default())
+    }
+
+    /// Context of `year` over `store` that drops its output and notes
+    pub(crate) fn context(store: impl InvoiceStore + 'static, year: i32) -> Context {
+        Context::new(Box::new(store), year, Box::new(io::sink()), Rc::new(RefCell::new(io::sink())))
+    }
+
+    /// Output shared with a context, to read back what a command wrote
+    #[derive(Clone, Default)]
+    struct Written(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Held by the tests working in the working directory, which is process-wide
+    static WORKING_DIR: Mutex<()> = Mutex::new(());
+
+    /// Runs `test` in an empty temp directory of its own
+    pub(crate) fn in_temp_dir(name: &str, test: impl FnOnce()) {
+        let _working_dir = WORKING_DIR.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = std::env::temp_dir().join(format!("accountant_tool_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+
+        test();
+
+        std::env::set_current_dir(cwd).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commands_write_to_the_output_of_the_context() {
+        let written = Written::default();
+        let context = Context::new(
+            Box::new(csv_store()),
+            2024,
+            Box::new(written.clone()),
+            Rc::new(RefCell::new(io::sink()))
+        );
+        let rules = Config::default().tax_rules(None).unwrap();
+
+        Invoice::rate_table(&context, 0.0, 2.0, 1.0, &rules, &MoneyFormat::neutral()).unwrap();
+
+        let written = String::from_utf8(written.0.take()).unwrap();
+        assert_eq!(written.lines().count(), 3);
+        assert!(written.starts_with("0.00: taxes 0.00, effective rate 0.00%"));
+    }
+
+    #[test]
+    fn backfill_fills_in_the_total_tax_of_a_file_without_it() {
+        in_temp_dir("backfill", || {
+            let store = csv_store();
+            std::fs::create_dir_all("invoices").unwrap();
+            std::fs::write(
+                store.year_file_path(2024),
+                "name,date,days_worked,daily_rate,currency,gross_profit,net_profit,government_tax,\
+                 social_contribution_tax\nold,1709251200,2,500,EUR,1000,699.25,200.5,100.25\n"
+            ).unwrap();
+            let context = context(store, 2024);
+            let rules = Config::default().tax_rules(None).unwrap();
+
+            Invoice::backfill(&context, false, true, &rules).unwrap();
+
+            let invoices = context.fetch_invoices().unwrap();
+            assert_eq!(invoices[0].total_tax, 30_075);
+            assert_eq!(invoices[0].net_profit, 69_925);
+        });
+    }
+
+    #[test]
+    fn export_banner_is_skipped_on_import() {
+        let mut config = Config::default();
+        config.issuer.name = Some("Jane Doe".to_string());
+        let rules = config.tax_rules(None).unwrap();
+        let format = CsvFormat::new(';', true, None).unwrap();
+        // A name starting with `#` is quoted, so it isn't taken for a comment
+        let commented = Invoice { name: "#2".to_string(), ..invoice(ymd(2024, 6, 1), 1) };
+        let exported = [invoice(ymd(2024, 5, 1), 125_000), commented];
+
+        let mut csv = Vec::new();
+        Invoice::write_export_banner(&mut csv, &config, &rules).unwrap();
+        storage::write_invoices_as(&mut csv, &exported, format, true).unwrap();
+        let text = String::from_utf8(csv.clone()).unwrap();
+        assert!(text.starts_with("# Invoices of Jane Doe\n# Generated "));
+        assert!(text.contains("\n# Tax profile default\n"));
+
+        let imported: Vec<Invoice> =
+            storage::invoice_rows(csv.as_slice(), format).unwrap().collect::<Result<_, _>>().unwrap();
+        let records: Vec<Vec<String>> = imported.iter().map(storage::to_record).collect();
+        assert_eq!(records, exported.iter().map(storage::to_record).collect::<Vec<_>>());
+    }
+}
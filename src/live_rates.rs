@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::error::InvoiceError;
+use crate::rates::{RateProvider, StaticRates};
+use crate::{note, Notes};
+
+/// Why the rates could not be fetched, the cached or static rates are used instead
+#[derive(Debug, Error)]
+enum FetchError {
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    #[error("the clock is before the epoch: {0}")]
+    Clock(#[from] SystemTimeError)
+}
+
+/// Rates fetched by the live provider, kept on disk so they can be used offline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateCache {
+    /// Seconds since the epoch the rates were fetched at
+    pub fetched_at: u64,
+    /// EUR value of one unit of a currency
+    pub rates: HashMap<String, f64>
+}
+
+impl RateCache {
+    /// Cache file in the platform cache directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("accountant_tool").join("rates.json"))
+    }
+
+    pub fn read(path: &Path) -> Option<RateCache> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), InvoiceError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// Whether the rates are younger than `ttl` at `now`
+    pub fn is_fresh(&self, now: SystemTime, ttl: Duration) -> bool {
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(self.fetched_at);
+
+        match now.duration_since(fetched_at) {
+            Ok(age) => age < ttl,
+            // Fetched "in the future", the clock moved back, so don't trust it
+            Err(_) => false,
+        }
+    }
+}
+
+/// Rates fetched from an HTTP endpoint, cached on disk for `ttl`.
+///
+/// The endpoint answers with the amount of each currency one EUR buys, e.g.
+/// `{"rates": {"USD": 1.08}}` as served by `https://api.frankfurter.app/latest?from=EUR`. When
+/// it can't be reached the last cached rates are used, and without a cache the static table.
+pub struct LiveRates {
+    rates: StaticRates,
+    fetched_at: Option<u64>
+}
+
+impl LiveRates {
+    pub const ENDPOINT: &'static str = "https://api.frankfurter.app/latest?from=EUR";
+
+    /// Why fresh rates could not be fetched or cached goes to `notes`
+    pub fn load(endpoint: &str, cache_path: &Path, ttl: Duration, fallback: StaticRates, notes: &Notes) -> Self {
+        let cache = RateCache::read(cache_path);
+
+        if let Some(cache) = cache.as_ref().filter(|cache| cache.is_fresh(SystemTime::now(), ttl)) {
+            return Self::from_cache(cache, fallback);
+        }
+
+        match Self::fetch(endpoint) {
+            Ok(fetched) => {
+                if let Err(err) = fetched.write(cache_path) {
+                    note(notes, &format!("Can not cache exchange rates: {}", err));
+                }
+                Self::from_cache(&fetched, fallback)
+            }
+            Err(err) => {
+                note(notes, &format!("Can not fetch exchange rates, using the last known ones: {}", err));
+                match cache {
+                    Some(cache) => Self::from_cache(&cache, fallback),
+                    None => LiveRates { rates: fallback, fetched_at: None },
+                }
+            }
+        }
+    }
+
+    fn from_cache(cache: &RateCache, fallback: StaticRates) -> Self {
+        LiveRates { rates: fallback.extended(&cache.rates), fetched_at: Some(cache.fetched_at) }
+    }
+
+    fn fetch(endpoint: &str) -> Result<RateCache, FetchError> {
+        #[derive(Deserialize)]
+        struct Response {
+            rates: HashMap<String, f64>
+        }
+
+        let response: Response = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?
+            .get(endpoint)
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        let rates = response.rates
+            .into_iter()
+            .filter(|(_, per_eur)| *per_eur > 0.0)
+            .map(|(currency, per_eur)| (currency.to_uppercase(), 1.0 / per_eur))
+            .collect();
+        let fetched_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        Ok(RateCache { fetched_at, rates })
+    }
+}
+
+impl RateProvider for LiveRates {
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        self.rates.rate(from, to)
+    }
+
+    fn eur_rates(&self) -> Vec<(String, f64)> {
+        self.rates.eur_rates()
+    }
+
+    fn updated_at(&self) -> Option<u64> {
+        self.fetched_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Read, Write};
+    use std::net::TcpListener;
+    use std::rc::Rc;
+    use std::thread;
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(3600);
+
+    /// Endpoint of a server on localhost answering a single request with `body`
+    fn serve(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                body.len(), body
+            )
+            .unwrap();
+        });
+
+        format!("http://{}/latest?from=EUR", address)
+    }
+
+    /// Endpoint nothing listens on
+    fn unreachable() -> String {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        format!("http://{}/latest?from=EUR", address)
+    }
+
+    /// Cache file of its own for each test, in the temp directory rather than the user's cache
+    fn cache_path(test: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("accountant_tool_{}_{}.json", std::process::id(), test));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn cache(fetched_at: u64, usd: f64) -> RateCache {
+        RateCache { fetched_at, rates: HashMap::from([("USD".to_string(), usd)]) }
+    }
+
+    fn notes() -> Notes {
+        Rc::new(RefCell::new(io::sink()))
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn fetched_rates_are_used_and_cached() {
+        let path = cache_path("fetched");
+
+        let rates = LiveRates::load(&serve(r#"{"rates": {"usd": 2.0}}"#), &path, TTL, StaticRates::default(), &notes());
+        assert_eq!(rates.rate("USD", "EUR"), Some(0.5));
+        assert!(rates.updated_at().is_some());
+        assert_eq!(RateCache::read(&path).unwrap().rates["USD"], 0.5);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fresh_cache_is_used_without_fetching() {
+        let path = cache_path("fresh");
+        cache(now(), 0.25).write(&path).unwrap();
+
+        let rates = LiveRates::load(&serve(r#"{"rates": {"USD": 2.0}}"#), &path, TTL, StaticRates::default(), &notes());
+        assert_eq!(rates.rate("USD", "EUR"), Some(0.25));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expired_cache_is_fetched_again() {
+        let path = cache_path("expired");
+        cache(now() - 2 * TTL.as_secs(), 0.25).write(&path).unwrap();
+
+        let rates = LiveRates::load(&serve(r#"{"rates": {"USD": 2.0}}"#), &path, TTL, StaticRates::default(), &notes());
+        assert_eq!(rates.rate("USD", "EUR"), Some(0.5));
+        assert!(RateCache::read(&path).unwrap().is_fresh(SystemTime::now(), TTL));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unreachable_endpoint_falls_back_on_the_cache_then_the_static_rates() {
+        let path = cache_path("fallback");
+        let fetched_at = now() - 2 * TTL.as_secs();
+        cache(fetched_at, 0.25).write(&path).unwrap();
+
+        let warnings = Rc::new(RefCell::new(Vec::new()));
+        let rates = LiveRates::load(&unreachable(), &path, TTL, StaticRates::default(), &(warnings.clone() as Notes));
+        assert_eq!(rates.rate("USD", "EUR"), Some(0.25));
+        assert!(String::from_utf8(warnings.take()).unwrap().starts_with("Can not fetch exchange rates"));
+        assert_eq!(rates.updated_at(), Some(fetched_at));
+
+        fs::remove_file(&path).unwrap();
+        let rates = LiveRates::load(&unreachable(), &path, TTL, StaticRates::default(), &notes());
+        assert_eq!(rates.rate("USD", "EUR"), StaticRates::default().rate("USD", "EUR"));
+        assert_eq!(rates.updated_at(), None);
+    }
+
+    #[test]
+    fn cache_from_the_future_is_not_fresh() {
+        let future = cache(now() + 60, 0.25);
+        assert!(!future.is_fresh(SystemTime::now(), TTL));
+        assert!(cache(now(), 0.25).is_fresh(SystemTime::now(), TTL));
+    }
+}
@@ -1,253 +1,693 @@
-use std::error::Error;
-use std::fs::{File, OpenOptions};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use csv::{ReaderBuilder, WriterBuilder};
-use chrono::prelude::{Local, DateTime};
-use chrono::Datelike;
-
-#[derive(Debug)]
-struct Invoice {
-    name: String,
-    date: u128,
-    days_worked: u8,
-    daily_rate: f64,
-    currency: String,
-    gross_profit: f64,
-    net_profit: f64,
-    government_tax: f64,
-    social_contribution_tax: f64,
-    total_tax: f64
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use chrono::{Datelike, Local, NaiveDate};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+
+use accountant_tool::config::{self, Config, TaxRules};
+use accountant_tool::error::InvoiceError;
+use accountant_tool::format::CurrencyStyle;
+use accountant_tool::hook::OnWrite;
+use accountant_tool::{
+    with_path, write_currencies, Basis, Context, CsvFormat, DocumentFormat, Invoice, NewInvoice, Notes, Quarter,
+    ReportFormat
+};
+
+const EXIT_CODES: &str = "Exit codes:
+  0  success
+  1  invalid input, e.g. a duplicate invoice name
+  2  a file could not be read or written
+  3  a file or value could not be parsed";
+
+#[derive(Parser)]
+#[command(version, about, after_help = EXIT_CODES)]
+struct Cli {
+    /// Config file to use instead of the one in the platform config directory
+    #[arg(long, global = true, env = "ACCOUNTANT_CONFIG")]
+    config: Option<PathBuf>,
+    /// Locale used to format amounts, e.g. `en` or `de`
+    #[arg(long, global = true)]
+    locale: Option<String>,
+    /// Number of decimals shown for amounts
+    #[arg(long, global = true)]
+    precision: Option<usize>,
+    /// Whether and where the currency is shown next to amounts
+    #[arg(long, global = true, value_enum)]
+    currency_style: Option<CurrencyStyle>,
+    /// Tax profile from the config to compute and report with, the default regime when left out
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Print nothing but errors after commands that change files
+    #[arg(long, short, global = true)]
+    quiet: bool,
+    /// Directory relative `--out` files are written to, the current directory when left out
+    #[arg(long, global = true)]
+    out_dir: Option<PathBuf>,
+    /// Year whose invoice file is added to and reported on, the current one when left out
+    #[arg(long, global = true, value_parser = clap::value_parser!(i32).range(1970..=9999))]
+    year: Option<i32>,
+    #[command(subcommand)]
+    command: Command
 }
 
-#[derive(Debug)]
-struct TaxBucket {
-    to: Option<u32>,
-    perc: f64
-}
-
-impl Invoice {
-    fn file_path() -> String {
-        let local: DateTime<Local> = Local::now();
-        let current_year = local.year();
-        let file_path = format!("src/invoices_{}.csv", current_year);
-
-        file_path
-    }
-
-    fn write_invoice_to_csv(invoice:Invoice) {
-        let file_path = Self::file_path();
-
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(true)
-            .open(file_path).unwrap();
-
-        let mut writer = WriterBuilder::new().from_writer(file);
-
-        let record = vec![
-            invoice.name,
-            invoice.date.to_string(),
-            invoice.days_worked.to_string(),
-            invoice.daily_rate.to_string(),
-            invoice.currency,
-            invoice.gross_profit.to_string(),
-            invoice.net_profit.to_string(),
-            invoice.government_tax.to_string(),
-            invoice.social_contribution_tax.to_string(),
-            invoice.total_tax.to_string(),
-        ];
-
-        let _ = writer.write_record(&record);
-        let empty_slice: &[&str] = &[];
-        let _ = writer.write_record(empty_slice);
-        let _ = writer.flush();
+#[derive(Subcommand)]
+enum Command {
+    /// Record a new invoice for the current year, or the one of `--year`
+    #[command(alias = "add")]
+    New {
+        #[command(flatten)]
+        invoice: NewArgs
+    },
+    /// Mark an estimated invoice as final
+    Confirm {
+        name: String
+    },
+    /// Print the totals for the current year
+    #[command(alias = "report")]
+    Info {
+        /// Count invoices in the year they were sent (accrual) or paid (cash)
+        #[arg(long, value_enum, default_value_t = Basis::Accrual)]
+        basis: Basis
+    },
+    /// Print the totals of the last twelve months, regardless of the calendar year
+    Ttm {
+        /// Last day of the twelve months as YYYY-MM-DD, defaults to today
+        #[arg(long)]
+        at: Option<NaiveDate>
+    },
+    /// Record that the client paid an invoice
+    Paid {
+        name: String,
+        /// Day of the payment as YYYY-MM-DD, defaults to today
+        #[arg(long)]
+        on: Option<NaiveDate>
+    },
+    /// Write the invoice document to send to the client
+    Render {
+        name: String,
+        /// File to write to, `-` for stdout
+        #[arg(long, default_value = "-")]
+        out: String,
+        /// Plain text, or an HTML page to print to PDF
+        #[arg(long, value_enum, default_value_t = DocumentFormat::Text)]
+        format: DocumentFormat
+    },
+    /// Show step by step how the taxes of an invoice are computed
+    Explain {
+        name: String
+    },
+    /// List the invoices of the year, oldest first
+    List,
+    /// Print every field of one invoice
+    Show {
+        name: String,
+        /// Print it as a JSON object
+        #[arg(long)]
+        json: bool
+    },
+    /// Split an invoice in two at a date, e.g. when work spans two tax years
+    Split {
+        name: String,
+        /// First day of the second part, as YYYY-MM-DD
+        #[arg(long)]
+        at: NaiveDate
+    },
+    /// Rename an invoice, its taxes stay the same
+    Rename {
+        old: String,
+        new: String
+    },
+    /// Correct an invoice and recalculate the taxes of the year
+    Edit {
+        name: String,
+        #[arg(long)]
+        days_worked: Option<f64>,
+        /// In the currency of the invoice
+        #[arg(long)]
+        daily_rate: Option<f64>,
+        #[arg(long)]
+        client: Option<String>
+    },
+    /// Delete an invoice and recalculate the taxes of the year
+    Delete {
+        name: String
+    },
+    /// Recompute the taxes of this year's invoices, e.g. after the brackets changed
+    Recalculate {
+        /// Only print what would change, without saving
+        #[arg(long)]
+        dry_run: bool
+    },
+    /// Fill in missing or zero derived amounts in all invoice files
+    Backfill {
+        /// Only print what would change, without saving
+        #[arg(long)]
+        dry_run: bool
+    },
+    /// Print the totals per month for the current year
+    Summary {
+        /// Write the summary as CSV to this file, `-` for stdout
+        #[arg(long)]
+        out: Option<String>,
+        /// Leave estimated invoices out of the recommended reserve
+        #[arg(long)]
+        exclude_estimated: bool
+    },
+    /// Write this year's invoices as CSV
+    Export {
+        /// File to write to, `-` for stdout
+        #[arg(long, default_value = "-")]
+        out: String,
+        /// Replace invoice names, clients and VAT numbers by placeholders, keeping amounts and dates
+        #[arg(long)]
+        anonymize: bool,
+        /// Write which placeholder stands for what to this CSV file, to undo the anonymizing later
+        #[arg(long, requires = "anonymize")]
+        mapping: Option<String>,
+        /// Leave out the header row, e.g. to append the rows to a spreadsheet that has one
+        #[arg(long)]
+        no_header: bool,
+        #[command(flatten)]
+        csv: CsvArgs
+    },
+    /// Add the invoices from a CSV export to the files of the years they are dated in
+    Import {
+        /// File to read from, `-` for stdin
+        #[arg(long = "in", default_value = "-")]
+        input: String,
+        #[command(flatten)]
+        csv: CsvArgs
+    },
+    /// Merge the invoices of another invoice file, e.g. from an old data directory, skipping the recorded ones
+    Merge {
+        /// Invoice file to merge, `-` for stdin
+        other: String
+    },
+    /// Move the invoice file of a year into the archive, so it no longer counts but can be restored
+    Archive {
+        year: i32,
+        /// Archive the current year too
+        #[arg(long)]
+        force: bool
+    },
+    /// Print the turnover and VAT collected per quarter, for the VAT return
+    VatReport {
+        /// Only this quarter, e.g. `Q2`
+        #[arg(long, value_enum, ignore_case = true)]
+        quarter: Option<Quarter>
+    },
+    /// Copy the invoices of the yearly CSV files into the `database` of the config
+    Migrate,
+    /// Print the version, data schema, tax profile and data directory, for bug reports
+    Version,
+    /// List the currencies invoices can be recorded in, with their EUR rates
+    Currencies,
+    /// Manage invoices that come back every month
+    Recurring {
+        #[command(subcommand)]
+        command: RecurringCommand
+    },
+    /// Record and list invoices, the same as `new` and `list`
+    Invoice {
+        #[command(subcommand)]
+        command: InvoiceCommand
+    },
+    /// Record and list the professional expenses deducted from the taxable profit
+    Expense {
+        #[command(subcommand)]
+        command: ExpenseCommand
+    },
+    /// Manage the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand
+    },
+    /// Print the gross, taxes and net a prospective contract adds on top of the invoices of the year
+    Quote {
+        #[arg(long)]
+        days: f64,
+        /// Daily rate in EUR, defaults to the configured daily rate
+        #[arg(long)]
+        rate: Option<f64>
+    },
+    /// Print the lowest daily rate that covers yearly living costs after taxes
+    #[command(alias = "breakeven")]
+    BreakevenRate {
+        /// Yearly living costs the net needs to cover
+        #[arg(long)]
+        annual_costs: f64,
+        /// Days a year that can be invoiced
+        #[arg(long)]
+        billable_days: f64
+    },
+    /// Project the year-end totals if the same days are invoiced every remaining week
+    Plan {
+        #[arg(long)]
+        days_per_week: f64,
+        /// Daily rate in EUR, defaults to the configured daily rate
+        #[arg(long)]
+        rate: Option<f64>
+    },
+    /// Recalculate this year's invoices under two config files and compare the taxes
+    CompareConfig {
+        a: PathBuf,
+        b: PathBuf
+    },
+    /// Print the taxes and effective rate over a range of yearly incomes
+    RateTable {
+        #[arg(long, default_value_t = 10_000.0)]
+        from: f64,
+        #[arg(long, default_value_t = 100_000.0)]
+        to: f64,
+        #[arg(long, default_value_t = 5_000.0)]
+        step: f64
+    },
+    /// Print how much of this year's income and government tax falls in each bracket
+    Brackets,
+    /// Write a yearly report for an accountant
+    AccountantExport {
+        /// File to write to, `-` for stdout
+        #[arg(long, default_value = "-")]
+        out: String,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat
+    },
+    /// List stretches of this year without any invoice
+    Gaps {
+        /// Only report stretches longer than this many days
+        #[arg(long)]
+        min_days: Option<i64>
+    },
+    /// List unpaid invoices past their due date under the configured payment terms
+    Overdue,
+    /// Rank this year's clients by revenue
+    TopClients {
+        #[arg(long, default_value_t = 10)]
+        limit: usize
+    },
+    /// Print the spread of this year's invoice sizes, with a histogram
+    Stats {
+        /// Number of histogram bins
+        #[arg(long, default_value_t = 8)]
+        bins: usize
+    },
+    /// Estimate the day on which this year's living costs are covered
+    BreakEven {
+        /// Monthly living costs to cover
+        #[arg(long)]
+        monthly_expenses: f64,
+        /// Share of gross profit reserved for taxes, defaults to the effective rate so far
+        #[arg(long)]
+        reserve_ratio: Option<f64>
+    },
+    /// Estimate the interest owed on quarterly tax prepayments made too late or short
+    PrepaymentCheck {
+        /// Prepayments made, one per quarter separated by commas, instead of those in the config
+        #[arg(long, value_delimiter = ',')]
+        paid: Option<Vec<f64>>
     }
+}
 
-    fn fetch_invoices() -> Result<Vec<Invoice>, Box<dyn Error>> {
-        let file_path = Self::file_path();
-        let mut invoices: Vec<Invoice> = Vec::new();
-
-        if !std::path::Path::new(&file_path).exists() {
-            let file = File::create(&file_path)?;
-            
-            let headers = vec![
-                "name", "date", "days_worked", "daily_rate", "currency",
-                "gross_profit", "net_profit", "government_tax",
-                "social_contribution_tax", "total_tax"
-            ];
-            let mut writer = WriterBuilder::new().from_writer(file);
-            writer.write_record(&headers)?;
-            return Ok(invoices);
-        }
-
-        let file = File::open(file_path)?;
-        let mut reader = ReaderBuilder::new().from_reader(file);
-
-        for result in reader.records() {
-            let record = result?;
-
-            let invoice = Invoice {
-                name: record[0].to_string(),
-                date: record[1].parse().unwrap(),
-                days_worked: record[2].parse().unwrap(),
-                daily_rate: record[3].parse().unwrap(),
-                currency: record[4].to_string(),
-                gross_profit: record[5].parse().unwrap(),
-                net_profit: record[6].parse().unwrap(),
-                government_tax: record[7].parse().unwrap(),
-                social_contribution_tax: record[8].parse().unwrap(),
-                total_tax: record[9].parse().unwrap(),
-            };
-    
-            invoices.push(invoice);
-        };
-
-        Ok(invoices)
-    }
+/// What `new` records, the name and days can be given as arguments or as `--name` and `--days-worked`
+#[derive(Args)]
+#[command(group(ArgGroup::new("invoice_name").required(true).args(["name", "name_flag"])))]
+#[command(group(ArgGroup::new("invoice_days").required(true).args(["days_worked", "days_worked_flag"])))]
+struct NewArgs {
+    /// Name of the invoice, unique among the invoices of all years
+    name: Option<String>,
+    /// Days worked at the daily rate
+    days_worked: Option<f64>,
+    /// The name, instead of as the first argument
+    #[arg(long = "name", id = "name_flag", value_name = "NAME")]
+    name_flag: Option<String>,
+    /// The days worked, instead of as the second argument
+    #[arg(long = "days-worked", id = "days_worked_flag", value_name = "DAYS_WORKED", visible_alias = "days")]
+    days_worked_flag: Option<f64>,
+    /// Days worked on top of `days_worked`, billed at the overtime multiplier
+    #[arg(long, default_value_t = 0.0)]
+    overtime_days: f64,
+    /// Factor of the daily rate the overtime days are billed at, e.g. `1.5`
+    #[arg(long, default_value_t = 1.0)]
+    overtime_multiplier: f64,
+    #[arg(long, visible_alias = "rate")]
+    daily_rate: Option<f64>,
+    #[arg(long)]
+    currency: Option<String>,
+    /// Client the work was done for
+    #[arg(long)]
+    client: Option<String>,
+    /// VAT number of the client, shown on the rendered invoice
+    #[arg(long)]
+    client_vat_id: Option<String>,
+    /// VAT charged on top, e.g. `0.21`, instead of the `vat_rate` of the config
+    #[arg(long)]
+    vat_rate: Option<f64>,
+    /// Charge no VAT, the client accounts for it, e.g. a business in another EU country
+    #[arg(long)]
+    reverse_charge: bool,
+    /// The days are not final yet, confirm the invoice once they are
+    #[arg(long)]
+    estimated: bool,
+    /// Day the work was invoiced as YYYY-MM-DD, defaults to now
+    #[arg(long)]
+    date: Option<NaiveDate>,
+    /// Add the invoice to the file of this year instead of the year it is dated in
+    #[arg(long)]
+    append_to: Option<i32>,
+    /// Record the invoice even if one with the same client, days, rate and currency exists
+    #[arg(long)]
+    force: bool
+}
 
-    fn tax_buckets() -> Vec<TaxBucket> {
-        vec![
-            TaxBucket {
-                to: Some(13_870),
-                perc: 0.25
-            },
-            TaxBucket {
-                to: Some(24_480),
-                perc: 0.40
-            },
-            TaxBucket {
-                to: Some(42_370),
-                perc: 0.45
-            },
-            TaxBucket {
-                to: None,
-                perc: 0.5
-            }
-        ]
+impl NewArgs {
+    /// The invoice to record, `selected_year` being the year of `--year`
+    fn invoice(self, selected_year: Option<i32>) -> Result<NewInvoice, InvoiceError> {
+        Ok(NewInvoice {
+            name: self.name.or(self.name_flag).ok_or_else(|| InvoiceError::invalid("name", "is required"))?,
+            days_worked: self
+                .days_worked
+                .or(self.days_worked_flag)
+                .ok_or_else(|| InvoiceError::invalid("days_worked", "is required"))?,
+            overtime_days: self.overtime_days,
+            overtime_multiplier: self.overtime_multiplier,
+            daily_rate: self.daily_rate,
+            currency: self.currency,
+            client: self.client,
+            client_vat_id: self.client_vat_id,
+            vat_rate: self.vat_rate,
+            reverse_charge: self.reverse_charge,
+            estimated: self.estimated,
+            date: self.date,
+            append_to: self.append_to,
+            // Without `--append-to` the invoice goes to the file `--year` selects, if any
+            selected_year,
+            force: self.force
+        })
     }
+}
 
-    fn appliable_tax_buckets(total_gross_profit:f64, mut gross_profit:f64) -> Vec<(f64, f64)> {
-        let mut appliable_tax_buckets = vec![];
-
-        let gross_profit_range = (total_gross_profit, total_gross_profit + &gross_profit);
-
-        let tax_buckets = Self::tax_buckets();
-        for tax_bucket in tax_buckets {
-            if tax_bucket.to == None {
-                appliable_tax_buckets.push((gross_profit, tax_bucket.perc));
-                return appliable_tax_buckets;
-            } else if gross_profit_range.0 > tax_bucket.to.unwrap() as f64 {
-                continue;
-            } else {
-                if gross_profit_range.1 < tax_bucket.to.unwrap() as f64 {
-                    appliable_tax_buckets.push((gross_profit, tax_bucket.perc));
-                    return appliable_tax_buckets;
-                } else {
-                    let diff = tax_bucket.to.unwrap() as f64 - gross_profit_range.1;
-                    appliable_tax_buckets.push((diff, tax_bucket.perc));
-                    gross_profit -= diff;
-                }
-            }
-        }
-        appliable_tax_buckets
-    }
-
-    fn calc_government_tax(appliable_tax_buckets: Vec<(f64, f64)>) -> (f64, f64) {
-        let mut profit_after_government_tax = 0.0;
-        let mut government_tax = 0.0;
-
-        for (gross_profit, tax) in appliable_tax_buckets {
-            government_tax += gross_profit * tax;
-            profit_after_government_tax += gross_profit - government_tax;
-        }
-
-        (profit_after_government_tax, government_tax)
-    }
+/// How the CSV file of `import` or `export` is laid out, for spreadsheets in other locales
+#[derive(Args)]
+struct CsvArgs {
+    /// Field separator, e.g. `;`
+    #[arg(long)]
+    delimiter: Option<char>,
+    /// Decimals are written with a comma, e.g. `1,5`
+    #[arg(long)]
+    decimal_comma: bool,
+    /// Character grouping thousands in numbers read by `import`, e.g. `,` for `1,234.56`
+    #[arg(long)]
+    thousands_separator: Option<char>
+}
 
-    fn calc_social_contribution(profit_after_government_tax: f64) -> (f64, f64) {
-        const SOCIAL_CONTRIBUTION_FEE: f64 = 0.205;
-        
-        let social_contribution = profit_after_government_tax * SOCIAL_CONTRIBUTION_FEE;
-        let net_profit = profit_after_government_tax - social_contribution;
-        
-        (net_profit, social_contribution)
+impl CsvArgs {
+    fn format(&self, config: &Config) -> Result<CsvFormat, InvoiceError> {
+        CsvFormat::new(
+            self.delimiter.or(config.csv_delimiter).unwrap_or(','),
+            self.decimal_comma || config.decimal_comma,
+            self.thousands_separator.or(config.thousands_separator)
+        )
     }
+}
 
-    fn calc_taxes(days_worked:u8, daily_rate:f64, invoices:Vec<Invoice>) -> (f64, f64, f64, f64) {
-        let total_gross_profit: f64 = invoices.iter().map(|record| record.gross_profit).sum();
-        let gross_profit = days_worked as f64 * daily_rate;
+#[derive(Subcommand)]
+enum RecurringCommand {
+    /// Add a recurring invoice to the config file
+    Add {
+        #[arg(long)]
+        name_prefix: String,
+        #[arg(long)]
+        days: f64,
+        #[arg(long)]
+        rate: Option<f64>,
+        #[arg(long)]
+        currency: Option<String>,
+        #[arg(long)]
+        client: Option<String>,
+        #[arg(long)]
+        client_vat_id: Option<String>,
+        /// Charge no VAT, the client accounts for it
+        #[arg(long)]
+        reverse_charge: bool,
+        /// Day of the month from which the invoice is generated
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=31))]
+        day_of_month: u32
+    },
+    /// Record this month's invoices of the recurring invoices that are due
+    Run
+}
 
-        let appliable_tax_buckets = Self::appliable_tax_buckets(total_gross_profit, gross_profit);
+// Parsed once per run, boxing `add` to make `list` smaller buys nothing
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+enum InvoiceCommand {
+    /// Record a new invoice, see `new`
+    Add {
+        #[command(flatten)]
+        invoice: NewArgs
+    },
+    /// List the invoices of the year, oldest first
+    List
+}
 
-        let (profit_after_government_tax, government_tax) = Self::calc_government_tax(appliable_tax_buckets);
-        let (net_profit, social_contribution) = Self::calc_social_contribution(profit_after_government_tax);
+#[derive(Subcommand)]
+enum ExpenseCommand {
+    /// Record an expense, recalculating the taxes of the invoices of its year
+    Add {
+        name: String,
+        /// Amount paid in EUR
+        amount: f64,
+        /// Day it was paid, defaults to today
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// Part of the amount that is deductible, e.g. `0.75` for a car also used privately
+        #[arg(long, default_value_t = 1.0)]
+        deductible_share: f64
+    },
+    /// List this year's expenses and what they deduct
+    List
+}
 
-        (gross_profit, net_profit, government_tax, social_contribution)
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Write a commented config template to the config path
+    Init,
+    /// Print the config file, or with `--effective` every setting in effect and where it came from
+    Show {
+        #[arg(long)]
+        effective: bool
     }
+}
 
-    pub fn new(name:String, days_worked:u8, daily_rate:Option<f64>, currency:Option<String>) {
-        const DAILY_RATE: f64 = 500.0;
-        const CURRENCY: &str = "EUR";
-
-        let invoices: Vec<Invoice> = Self::fetch_invoices().unwrap();
-        
-        if invoices.iter().any(|invoice| invoice.name == name) {
-            panic!("`name` needs to be unique from other invoices");
-        } else if days_worked == 0 {
-            panic!("`days_worked` can not be 0");
-        } else if daily_rate == Some(0.0) {
-            panic!("`daily_rate` can not be 0.0");
-        }
-
-        let daily_rate = daily_rate.unwrap_or(DAILY_RATE);
-        let currency = currency.unwrap_or(CURRENCY.to_string()).to_uppercase();
-        // TODO: If currency != EUR, convert `daily_rate` to EUR
-
-        let (gross_profit, net_profit, government_tax, social_contribution_tax) =
-            Self::calc_taxes(days_worked, daily_rate, invoices);
-
-        let current_timestamp= SystemTime::now();
-        let since_the_epoch = current_timestamp .duration_since(UNIX_EPOCH).expect("Time went backwards");
-        let timestamp_millis  = since_the_epoch.as_millis();
-        
-        let invoice = Invoice {
-            name,
-            date: timestamp_millis,
-            days_worked,
-            daily_rate,
-            currency,
-            gross_profit,
-            net_profit,
-            government_tax,
-            social_contribution_tax,
-            total_tax: government_tax + social_contribution_tax
+fn run() -> Result<(), InvoiceError> {
+    let cli = Cli::parse();
+
+    let config_source = match (&cli.config, std::env::var_os("ACCOUNTANT_CONFIG")) {
+        (Some(path), Some(env)) if path.as_os_str() == env => "ACCOUNTANT_CONFIG",
+        (Some(_), _) => "--config",
+        (None, _) => "platform config directory",
+    };
+    let config_path = cli.config.or_else(config::default_path);
+    let config = config::load(config_path.as_deref())?;
+
+    let flags = config::Flags {
+        locale: cli.locale.as_deref(),
+        precision: cli.precision,
+        currency_style: cli.currency_style,
+        out_dir: cli.out_dir.as_deref()
+    };
+    let money = config.money_format(flags)?;
+
+    let notes: Notes = Rc::new(RefCell::new(io::stderr()));
+    let rates = config.rate_provider(&notes)?;
+    let tax_rules = config.tax_rules(cli.profile.as_deref())?;
+    let on_write = OnWrite::new(config.on_write.clone(), notes.clone());
+    let store = config.invoice_store(on_write.clone(), &notes)?;
+    let year = cli.year.unwrap_or_else(|| Local::now().year());
+    let context = Context::new(store, year, Box::new(io::stdout()), notes);
+
+    // Generated files go to the export directory, never next to the invoice files unless asked to
+    let out_dir = cli.out_dir.clone().or(config.export_dir.clone());
+    let output = |out: &str| -> Result<String, InvoiceError> {
+        let Some(dir) = out_dir.as_ref().filter(|_| out != "-" && Path::new(out).is_relative()) else {
+            return Ok(out.to_string());
         };
-        
-        Self::write_invoice_to_csv(invoice);
-    }
+        std::fs::create_dir_all(dir).map_err(|err| with_path(err, &dir.display().to_string()))?;
+        Ok(dir.join(out).display().to_string())
+    };
 
-    pub fn accountant_info() {
-        let invoices: Vec<Invoice> = Self::fetch_invoices().unwrap();
+    let no_config_path = || InvoiceError::Validation("no config directory found, pass one with `--config`".to_string());
 
-        let total_gross_profit: f64 = invoices.iter().map(|record| record.gross_profit).sum();
-        let total_net_profit: f64 = invoices.iter().map(|record| record.net_profit).sum();
-        let total_gov_tax: f64 = invoices.iter().map(|record| record.government_tax).sum();
-        let total_social_contribution: f64 = invoices.iter().map(|record| record.social_contribution_tax).sum();
-        let total_tax: f64 = invoices.iter().map(|record| record.total_tax).sum();
-
-        println!("Total gross profit: {}", total_gross_profit);
-        println!("Total net profit: {}", total_net_profit);
-        println!("Total government tax: {}", total_gov_tax);
-        println!("Total social contribution: {}", total_social_contribution);
-        println!("Total taxes: {}", total_tax);
+    match cli.command {
+        Command::New { invoice } | Command::Invoice { command: InvoiceCommand::Add { invoice } } => {
+            let new = invoice.invoice(cli.year)?;
+            Invoice::new(&context, new, &config, &tax_rules, rates.as_ref(), &money, cli.quiet)
+        }
+        Command::Confirm { name } => Invoice::confirm(&context, &name),
+        Command::Info { basis } => {
+            Invoice::accountant_info(&context, basis, config.hours_per_day.unwrap_or(Invoice::HOURS_PER_DAY), &money)
+        }
+        Command::Ttm { at } => {
+            Invoice::trailing_twelve_months(&context, at.unwrap_or_else(|| Local::now().date_naive()), &money)
+        }
+        Command::Paid { name, on } => {
+            Invoice::mark_paid(&context, &name, on.unwrap_or_else(|| Local::now().date_naive()))
+        }
+        Command::Render { name, out, format } => {
+            Invoice::render(&context, &name, &output(&out)?, format, &config, &money)
+        }
+        Command::Explain { name } => Invoice::explain(&context, &name, &tax_rules, &money),
+        Command::List | Command::Invoice { command: InvoiceCommand::List } => Invoice::list(&context, &money),
+        Command::Show { name, json } => Invoice::show(&context, &name, json, &money),
+        Command::Split { name, at } => Invoice::split(&context, &name, at, &tax_rules),
+        Command::Rename { old, new } => Invoice::rename(&context, &old, &new),
+        Command::Edit { name, days_worked, daily_rate, client } => {
+            Invoice::update(&context, &name, days_worked, daily_rate, client, &config, &tax_rules, rates.as_ref())
+        }
+        Command::Delete { name } => Invoice::delete(&context, &name, &tax_rules),
+        Command::Recalculate { dry_run } => Invoice::recalculate(&context, dry_run, cli.quiet, &tax_rules, &money),
+        Command::Backfill { dry_run } => Invoice::backfill(&context, dry_run, cli.quiet, &tax_rules),
+        Command::Summary { out, exclude_estimated } => {
+            let out = out.map(|out| output(&out)).transpose()?;
+            Invoice::monthly_summary(&context, out.as_deref(), exclude_estimated, &tax_rules, &money)
+        }
+        Command::Export { out, anonymize, mapping, no_header, csv } => {
+            let mapping = mapping.map(|mapping| output(&mapping)).transpose()?;
+            Invoice::export(
+                &context,
+                &output(&out)?,
+                csv.format(&config)?,
+                !no_header,
+                anonymize,
+                mapping.as_deref(),
+                &config,
+                &tax_rules
+            )
+        }
+        Command::Import { input, csv } => Invoice::import(&context, &input, csv.format(&config)?, cli.quiet),
+        Command::Merge { other } => Invoice::merge(&context, &other, &tax_rules, cli.quiet),
+        Command::Archive { year, force } => Invoice::archive(&context, year, force, cli.quiet),
+        Command::VatReport { quarter } => Invoice::vat_report(&context, quarter, &money),
+        Command::Migrate => match config.database {
+            Some(_) => Invoice::migrate(&context, &config.csv_store(on_write)?, cli.quiet),
+            None => Err(InvoiceError::Validation(
+                "set `database` in the config to migrate the CSV files into".to_string()
+            )),
+        },
+        Command::Version => Invoice::version_report(&context, &tax_rules, config_path.as_deref()),
+        Command::Currencies => Ok(write_currencies(&mut *context.out(), rates.as_ref())?),
+        Command::Recurring { command: RecurringCommand::Add {
+            name_prefix, days, rate, currency, client, client_vat_id, reverse_charge, day_of_month
+        } } => {
+            let path = config_path.ok_or_else(no_config_path)?;
+            let recurring = config::Recurring {
+                name_prefix,
+                days_worked: days,
+                daily_rate: rate,
+                currency,
+                client,
+                client_vat_id,
+                reverse_charge,
+                day_of_month,
+                active: true
+            };
+            config::add_recurring(&path, recurring)
+        }
+        Command::Recurring { command: RecurringCommand::Run } => {
+            Invoice::run_recurring(&context, &config, &tax_rules, rates.as_ref(), &money, cli.quiet)
+        }
+        Command::Expense { command: ExpenseCommand::Add { name, amount, date, deductible_share } } => {
+            let date = date.unwrap_or_else(|| Local::now().date_naive());
+            Invoice::add_expense(&context, &name, amount, date, deductible_share, &tax_rules, &money, cli.quiet)
+        }
+        Command::Expense { command: ExpenseCommand::List } => Invoice::list_expenses(&context, &money),
+        Command::Config { command: ConfigCommand::Init } => {
+            let path = config_path.ok_or_else(no_config_path)?;
+            config::write_template(&path)?;
+            if !cli.quiet {
+                writeln!(context.out(), "Wrote config template to {}", path.display())?;
+            }
+            Ok(())
+        }
+        Command::Config { command: ConfigCommand::Show { effective: true } } => {
+            config::write_effective(
+                &mut *context.out(),
+                config_path.as_deref(),
+                config_source,
+                flags,
+                &config,
+                &tax_rules,
+                rates.as_ref(),
+                context.year
+            )
+        }
+        Command::Config { command: ConfigCommand::Show { effective: false } } => {
+            let path = config_path.ok_or_else(no_config_path)?;
+            if !path.exists() {
+                return Err(InvoiceError::Validation(format!(
+                    "no config at `{}`, create one with `config init`", path.display()
+                )));
+            }
+            let config = std::fs::read_to_string(&path).map_err(|err| with_path(err, &path.display().to_string()))?;
+            write!(context.out(), "{}", config)?;
+            Ok(())
+        }
+        Command::Brackets => Invoice::bracket_report(&context, &tax_rules, &money),
+        Command::Quote { days, rate } => {
+            let rate = rate.or(config.daily_rate).unwrap_or(Invoice::DAILY_RATE);
+            Invoice::quote(&context, days, rate, &tax_rules, &money)
+        }
+        Command::BreakevenRate { annual_costs, billable_days } => {
+            Invoice::breakeven_rate(&context, annual_costs, billable_days, &tax_rules, &money)
+        }
+        Command::Plan { days_per_week, rate } => {
+            let rate = rate.or(config.daily_rate).unwrap_or(Invoice::DAILY_RATE);
+            Invoice::plan(&context, days_per_week, rate, &tax_rules, &money)
+        }
+        Command::CompareConfig { a, b } => {
+            // A missing file would load as the defaults, which is not what is being compared
+            let rules = |path: &Path| -> Result<TaxRules, InvoiceError> {
+                if !path.exists() {
+                    return Err(InvoiceError::Validation(format!("config `{}` does not exist", path.display())));
+                }
+                config::load(Some(path))?.tax_rules(cli.profile.as_deref())
+            };
+            let (rules_a, rules_b) = (rules(&a)?, rules(&b)?);
+            let (a, b) = (a.display().to_string(), b.display().to_string());
+            Invoice::compare_rules(&context, &a, &rules_a, &b, &rules_b, &money)
+        }
+        Command::RateTable { from, to, step } => Invoice::rate_table(&context, from, to, step, &tax_rules, &money),
+        Command::AccountantExport { out, format } => {
+            Invoice::accountant_export(&context, &output(&out)?, format, &tax_rules, &money)
+        }
+        Command::Gaps { min_days } => Invoice::gaps_report(&context, min_days.or(config.gap_days)),
+        Command::Overdue => Invoice::overdue_report(&context, &config.payment_terms, &config.holidays, &money),
+        Command::TopClients { limit } => Invoice::top_clients_report(&context, limit, &money),
+        Command::Stats { bins } => Invoice::stats_report(&context, bins, &money),
+        Command::BreakEven { monthly_expenses, reserve_ratio } => {
+            Invoice::break_even_report(&context, monthly_expenses, reserve_ratio, &tax_rules)
+        }
+        Command::PrepaymentCheck { paid } => Invoice::prepayment_check(
+            &context,
+            context.year,
+            paid.as_deref(),
+            &config.prepayments,
+            &tax_rules,
+            &money
+        ),
     }
 }
 
-// TODO: Turn into a CLI
-
 fn main() {
-    Invoice::new("test_invoice_1".to_string(), 5, Some(500.0), Some("EUR".to_string()));
-    Invoice::accountant_info();
+    match run() {
+        Ok(()) => {}
+        // The reader went away, e.g. `| head`, there's no one left to tell
+        Err(InvoiceError::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(err.exit_code());
+        }
+    }
 }
\ No newline at end of file
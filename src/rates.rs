@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use csv::ReaderBuilder;
+
+use crate::error::InvoiceError;
+use crate::BASE_CURRENCY;
+
+/// ISO 4217 codes of the currencies in circulation, sorted
+const ISO_4217: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB",
+    "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XCG", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG"
+];
+
+/// Whether `code` is an ISO 4217 currency code or one of the `custom` codes allowed in the config
+pub fn is_known_currency(code: &str, custom: &[String]) -> bool {
+    ISO_4217.binary_search(&code).is_ok() || custom.iter().any(|custom| custom.eq_ignore_ascii_case(code))
+}
+
+/// EUR value of `amount` of `from`, an error rather than the unconverted amount when no usable rate
+/// is known, since taxing it as if it were in EUR gives wrong figures.
+pub fn convert_to_eur(rates: &dyn RateProvider, amount: f64, from: &str) -> Result<f64, InvoiceError> {
+    match rates.rate(from, "EUR").filter(|rate| *rate > 0.0 && rate.is_finite()) {
+        Some(rate) => Ok(amount * rate),
+        None => Err(InvoiceError::Validation(format!(
+            "no exchange rate from `{}` to EUR, add one to the `rates_file` to record invoices in it",
+            from
+        ))),
+    }
+}
+
+/// Source of exchange rates between currencies.
+pub trait RateProvider {
+    /// Amount of `to` one unit of `from` is worth, `None` when the rate is unknown
+    fn rate(&self, from: &str, to: &str) -> Option<f64>;
+
+    /// EUR value of one unit of every currency the provider knows, sorted by code
+    fn eur_rates(&self) -> Vec<(String, f64)>;
+
+    /// Seconds since the epoch the rates were last updated, `None` for a fixed table
+    fn updated_at(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Why the base currency can not be given a rate
+const BASE_RATE: &str = "the currency every rate is relative to";
+
+/// Fixed table of how many EUR one unit of a currency is worth.
+#[derive(Debug, Clone)]
+pub struct StaticRates {
+    rates: HashMap<String, f64>
+}
+
+impl StaticRates {
+    pub fn new(rates: HashMap<String, f64>) -> Self {
+        let mut rates: HashMap<String, f64> = rates
+            .into_iter()
+            .map(|(currency, rate)| (currency.to_uppercase(), rate))
+            .collect();
+        rates.insert(BASE_CURRENCY.to_string(), 1.0);
+
+        StaticRates { rates }
+    }
+
+    /// Reads `currency,rate` rows under that header, e.g. a `rates.csv` kept next to the invoices.
+    ///
+    /// The header is required, without it the first currency would be taken for one.
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self, InvoiceError> {
+        let mut rates = HashMap::new();
+        let mut reader = ReaderBuilder::new().from_reader(reader);
+
+        let headers: Vec<String> = reader.headers()?.iter().map(|header| header.trim().to_lowercase()).collect();
+        if headers != ["currency", "rate"] {
+            return Err(InvoiceError::Parse(format!(
+                "a rates file starts with a `currency,rate` header, not `{}`", headers.join(",")
+            )));
+        }
+
+        for result in reader.records() {
+            let record = result?;
+            let currency = record.get(0).unwrap_or("").trim();
+            let rate = record.get(1).unwrap_or("").trim();
+            let rate: f64 = rate.parse()
+                .ok()
+                .filter(|rate: &f64| *rate > 0.0 && rate.is_finite())
+                .ok_or_else(|| InvoiceError::Parse(format!("invalid rate `{}` for `{}`", rate, currency)))?;
+            if currency.eq_ignore_ascii_case(BASE_CURRENCY) {
+                return Err(InvoiceError::Parse(format!("a rates file can not hold {}, {}", BASE_CURRENCY, BASE_RATE)));
+            }
+            rates.insert(currency.to_string(), rate);
+        }
+
+        Ok(Self::new(rates))
+    }
+
+    /// Overrides or adds the rates of `rates` on top of this table, e.g. the `rates` of the config.
+    pub fn with_rates(self, rates: &HashMap<String, f64>) -> Result<Self, InvoiceError> {
+        for (currency, rate) in rates {
+            if currency.eq_ignore_ascii_case(BASE_CURRENCY) {
+                return Err(InvoiceError::Validation(format!("`rates` can not set {}, {}", BASE_CURRENCY, BASE_RATE)));
+            } else if *rate <= 0.0 || !rate.is_finite() {
+                return Err(InvoiceError::Validation(format!("`rates.{}` needs to be more than 0", currency)));
+            }
+        }
+
+        Ok(self.extended(rates))
+    }
+
+    /// `rates` added on top of this table, any rate for the base currency left out
+    pub(crate) fn extended(mut self, rates: &HashMap<String, f64>) -> Self {
+        for (currency, rate) in rates {
+            if !currency.eq_ignore_ascii_case(BASE_CURRENCY) {
+                self.rates.insert(currency.to_uppercase(), *rate);
+            }
+        }
+        self
+    }
+}
+
+impl Default for StaticRates {
+    /// Approximate reference rates, override them in the config to keep them current.
+    fn default() -> Self {
+        let rates = [
+            ("USD", 0.92),
+            ("GBP", 1.17),
+            ("CHF", 1.04),
+            ("JPY", 0.0061),
+            ("CAD", 0.68),
+            ("AUD", 0.61),
+            ("SEK", 0.088),
+            ("NOK", 0.087),
+            ("DKK", 0.134),
+            ("PLN", 0.23),
+        ];
+
+        Self::new(rates.into_iter().map(|(currency, rate)| (currency.to_string(), rate)).collect())
+    }
+}
+
+impl RateProvider for StaticRates {
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+
+        let from = self.rates.get(from)?;
+        let to = self.rates.get(to)?;
+
+        Some(from / to)
+    }
+
+    fn eur_rates(&self) -> Vec<(String, f64)> {
+        let mut rates: Vec<(String, f64)> = self.rates.iter().map(|(currency, rate)| (currency.clone(), *rate)).collect();
+        rates.sort_by(|a, b| a.0.cmp(&b.0));
+
+        rates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_file_needs_its_header() {
+        let rates = StaticRates::from_csv("currency,rate\nUSD,0.9\nGBP,1.2\n".as_bytes()).unwrap();
+        assert_eq!(rates.rate("USD", "EUR"), Some(0.9));
+        assert_eq!(rates.rate("GBP", "EUR"), Some(1.2));
+
+        // Read with a header the first row would be lost, so it is refused instead
+        assert!(StaticRates::from_csv("USD,0.9\nGBP,1.2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn base_currency_can_not_be_overridden() {
+        assert!(StaticRates::from_csv("currency,rate\nEUR,2.0\n".as_bytes()).is_err());
+
+        let overrides = HashMap::from([("eur".to_string(), 2.0)]);
+        assert!(StaticRates::default().with_rates(&overrides).is_err());
+
+        let overrides = HashMap::from([("usd".to_string(), 0.8)]);
+        let rates = StaticRates::default().with_rates(&overrides).unwrap();
+        assert_eq!(rates.rate("USD", "EUR"), Some(0.8));
+        assert_eq!(rates.rate("EUR", "USD"), Some(1.0 / 0.8));
+    }
+}
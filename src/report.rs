@@ -0,0 +1,986 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use csv::WriterBuilder;
+use serde::Serialize;
+
+use crate::cents::{self, Cents};
+use crate::config::{BracketPeriod, SocialBase, TaxRules};
+use crate::error::InvoiceError;
+use crate::expense::{self, Expense};
+use crate::format::MoneyFormat;
+use crate::tax::{self, TaxBucket};
+use crate::Invoice;
+
+#[derive(Debug, Default, Clone)]
+pub struct MonthlySummary {
+    pub month: u32,
+    pub gross_profit: Cents,
+    pub net_profit: Cents,
+    pub total_tax: Cents,
+    pub tax_reserve: Cents,
+    /// Part of the gross profit from invoices that are still estimates
+    pub estimated_gross_profit: Cents
+}
+
+/// Totals of every month of `year`. The tax reserve covers the part of the income the `expenses`
+/// don't, with `exclude_estimated` only that of confirmed invoices.
+pub fn monthly_summary(
+    year: i32,
+    invoices: &[Invoice],
+    expenses: &[Expense],
+    rules: &TaxRules,
+    exclude_estimated: bool
+) -> Vec<MonthlySummary> {
+    let mut months: Vec<MonthlySummary> = (1..=12)
+        .map(|month| MonthlySummary { month, ..Default::default() })
+        .collect();
+    let mut reserved_taxable_profits: [Cents; 12] = [0; 12];
+
+    for (invoice, taxable_profit) in invoices.iter().zip(expense::taxable_profits(invoices, expenses)) {
+        let month0 = invoice.local_date().month0() as usize;
+        let month = &mut months[month0];
+        month.gross_profit += invoice.gross_profit;
+        month.net_profit += invoice.net_profit;
+        month.total_tax += invoice.total_tax;
+        if invoice.estimated {
+            month.estimated_gross_profit += invoice.gross_profit;
+        }
+        if !(exclude_estimated && invoice.estimated) {
+            reserved_taxable_profits[month0] += taxable_profit;
+        }
+    }
+
+    let mut cumulative_taxable_profit = 0;
+    for (month, reserved_taxable_profit) in months.iter_mut().zip(reserved_taxable_profits) {
+        cumulative_taxable_profit += reserved_taxable_profit;
+        let tax_buckets = NaiveDate::from_ymd_opt(year, month.month, 1)
+            .map_or_else(tax::default_tax_buckets, |first_day| tax::tax_buckets(&rules.brackets, first_day));
+        month.tax_reserve =
+            recommended_tax_reserve(&tax_buckets, rules, reserved_taxable_profit, cumulative_taxable_profit);
+    }
+
+    months
+}
+
+/// Amount of `taxable_profit` to set aside, taxed at the marginal rate reached at
+/// `cumulative_taxable_profit`.
+///
+/// Because the brackets are progressive, euros earned late in the year are taxed heavier than the
+/// yearly average, so reserving at the marginal rate avoids falling short at filing time.
+pub fn recommended_tax_reserve(
+    tax_buckets: &[TaxBucket],
+    rules: &TaxRules,
+    taxable_profit: Cents,
+    cumulative_taxable_profit: Cents
+) -> Cents {
+    cents::apply_rate(taxable_profit, tax::marginal_tax_rate(tax_buckets, rules, cumulative_taxable_profit))
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct QuarterlySummary {
+    pub quarter: u32,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub gross_profit: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub net_profit: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub government_tax: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub social_contribution_tax: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_tax: Cents
+}
+
+pub fn quarterly_summary(invoices: &[Invoice]) -> Vec<QuarterlySummary> {
+    let mut quarters: Vec<QuarterlySummary> = (1..=4)
+        .map(|quarter| QuarterlySummary { quarter, ..Default::default() })
+        .collect();
+
+    for invoice in invoices {
+        let quarter = &mut quarters[invoice.local_date().month0() as usize / 3];
+        quarter.gross_profit += invoice.gross_profit;
+        quarter.net_profit += invoice.net_profit;
+        quarter.government_tax += invoice.government_tax;
+        quarter.social_contribution_tax += invoice.social_contribution_tax;
+        quarter.total_tax += invoice.total_tax;
+    }
+
+    quarters
+}
+
+/// Quarter of a prepayment check, with the interest on what was left unpaid after its due date.
+#[derive(Debug, Clone)]
+pub struct PrepaymentQuarter {
+    pub quarter: u32,
+    pub due: NaiveDate,
+    pub tax_due: Cents,
+    pub paid: Cents,
+    /// Tax of this and earlier quarters not prepaid by the due date
+    pub shortfall: Cents,
+    /// Days the shortfall ran on, up to the next due date or the end of the year
+    pub days: i64,
+    pub interest: Cents
+}
+
+/// Compares the prepayments made against the tax of each quarter, charging `penalty_rate` a year on
+/// what was paid short for as long as it stayed short. Nothing runs on past `today`.
+///
+/// Paying more in a later quarter covers an earlier shortfall from then on, but not before.
+pub fn prepayment_check(
+    quarters: &[QuarterlySummary],
+    paid: &[Cents],
+    due: &[NaiveDate],
+    penalty_rate: f64,
+    today: NaiveDate
+) -> Vec<PrepaymentQuarter> {
+    let mut cumulative_tax = 0;
+    let mut cumulative_paid = 0;
+
+    quarters
+        .iter()
+        .zip(due)
+        .enumerate()
+        .map(|(index, (quarter, &due_date))| {
+            let quarter_paid = paid.get(index).copied().unwrap_or(0);
+            cumulative_tax += quarter.total_tax;
+            cumulative_paid += quarter_paid;
+
+            let year_end = NaiveDate::from_ymd_opt(due_date.year(), 12, 31).unwrap();
+            let until = due.get(index + 1).copied().unwrap_or(year_end).min(today);
+            let shortfall = (cumulative_tax - cumulative_paid).max(0);
+            let days = (until - due_date).num_days().max(0);
+
+            PrepaymentQuarter {
+                quarter: quarter.quarter,
+                due: due_date,
+                tax_due: quarter.total_tax,
+                paid: quarter_paid,
+                shortfall,
+                days,
+                interest: cents::apply_rate(shortfall, penalty_rate * days as f64 / 365.0)
+            }
+        })
+        .collect()
+}
+
+/// Turnover billed at one VAT rate and the VAT charged on it
+#[derive(Debug, Clone)]
+pub struct VatAtRate {
+    pub rate: f64,
+    pub turnover: Cents,
+    pub vat: Cents
+}
+
+/// What the VAT return of a quarter asks for, the turnover split by how its VAT is accounted for.
+#[derive(Debug, Default, Clone)]
+pub struct VatQuarter {
+    pub quarter: u32,
+    /// Lowest rate first, a rate of 0 for turnover exempt from VAT
+    pub rates: Vec<VatAtRate>,
+    /// Turnover of reverse-charged invoices, the client accounts for its VAT
+    pub reverse_charged: Cents,
+    /// Invoices left out because their days are not final yet
+    pub estimated_count: usize
+}
+
+impl VatQuarter {
+    pub fn vat(&self) -> Cents {
+        self.rates.iter().map(|rate| rate.vat).sum()
+    }
+}
+
+/// Totals the VAT of each quarter. Estimated invoices are only counted, they are not billed yet.
+pub fn vat_quarters(invoices: &[Invoice]) -> Vec<VatQuarter> {
+    let mut quarters: Vec<VatQuarter> = (1..=4)
+        .map(|quarter| VatQuarter { quarter, ..Default::default() })
+        .collect();
+
+    for invoice in invoices {
+        let quarter = &mut quarters[invoice.local_date().month0() as usize / 3];
+        if invoice.estimated {
+            quarter.estimated_count += 1;
+        } else if invoice.reverse_charge {
+            quarter.reverse_charged += invoice.gross_profit;
+        } else {
+            match quarter.rates.iter_mut().find(|rate| rate.rate == invoice.vat_rate) {
+                Some(rate) => {
+                    rate.turnover += invoice.gross_profit;
+                    rate.vat += invoice.vat;
+                }
+                None => quarter.rates.push(VatAtRate {
+                    rate: invoice.vat_rate,
+                    turnover: invoice.gross_profit,
+                    vat: invoice.vat
+                }),
+            }
+        }
+    }
+    for quarter in &mut quarters {
+        quarter.rates.sort_by(|a, b| a.rate.total_cmp(&b.rate));
+    }
+
+    quarters
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BracketTotal {
+    pub perc: f64,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub gross_profit: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub government_tax: Cents
+}
+
+/// Government tax of the year per bracket, the taxable profit of every invoice taxed in date order
+/// on top of that of the invoices before it. Brackets of a later period that have a rate of their
+/// own are added after those in effect at the start of the year.
+pub fn bracket_totals(
+    year: i32,
+    invoices: &[Invoice],
+    expenses: &[Expense],
+    periods: &[BracketPeriod]
+) -> Vec<BracketTotal> {
+    let mut totals: Vec<BracketTotal> = Vec::new();
+    for period in tax::year_bracket_periods(periods, year) {
+        for tax_bucket in period.buckets {
+            if !totals.iter().any(|total| total.perc == tax_bucket.perc) {
+                totals.push(BracketTotal { perc: tax_bucket.perc, gross_profit: 0, government_tax: 0 });
+            }
+        }
+    }
+
+    let taxable_profits = expense::taxable_profits(invoices, expenses);
+    let mut sorted: Vec<(&Invoice, Cents)> = invoices.iter().zip(taxable_profits).collect();
+    sorted.sort_by_key(|(invoice, _)| invoice.date);
+
+    let mut cumulative_taxable_profit = 0;
+    for (invoice, taxable_profit) in sorted {
+        let tax_buckets = tax::tax_buckets(periods, invoice.local_date().date_naive());
+        let appliable_tax_buckets =
+            tax::appliable_tax_buckets(&tax_buckets, cumulative_taxable_profit, taxable_profit);
+        for (gross_profit, perc) in appliable_tax_buckets {
+            if let Some(total) = totals.iter_mut().find(|total| total.perc == perc) {
+                total.gross_profit += gross_profit;
+                total.government_tax += cents::apply_rate(gross_profit, perc);
+            }
+        }
+        cumulative_taxable_profit += taxable_profit;
+    }
+
+    totals
+}
+
+/// Running totals over invoices, added one invoice at a time so the invoices need not be kept.
+#[derive(Debug, Default, Clone)]
+pub struct InvoiceTotals {
+    pub count: usize,
+    /// Days worked, overtime days included
+    pub days_worked: f64,
+    pub gross_profit: Cents,
+    pub net_profit: Cents,
+    pub government_tax: Cents,
+    pub social_contribution_tax: Cents,
+    pub total_tax: Cents,
+    pub estimated_count: usize,
+    pub estimated_gross_profit: Cents,
+    /// Gross profit and total tax per profile, the default regime under an empty name
+    pub profiles: BTreeMap<String, (Cents, Cents)>,
+    first_date: Option<u128>,
+    last_date: Option<u128>
+}
+
+impl InvoiceTotals {
+    pub fn add(&mut self, invoice: &Invoice) {
+        self.count += 1;
+        self.days_worked += invoice.days_worked + invoice.overtime_days;
+        self.gross_profit += invoice.gross_profit;
+        self.net_profit += invoice.net_profit;
+        self.government_tax += invoice.government_tax;
+        self.social_contribution_tax += invoice.social_contribution_tax;
+        self.total_tax += invoice.total_tax;
+        if invoice.estimated {
+            self.estimated_count += 1;
+            self.estimated_gross_profit += invoice.gross_profit;
+        }
+
+        let profile = self.profiles.entry(invoice.profile.clone()).or_default();
+        profile.0 += invoice.gross_profit;
+        profile.1 += invoice.total_tax;
+
+        self.first_date = Some(self.first_date.map_or(invoice.date, |date| date.min(invoice.date)));
+        self.last_date = Some(self.last_date.map_or(invoice.date, |date| date.max(invoice.date)));
+    }
+
+    /// Share of the gross profit that went to taxes, 0 when nothing was earned yet
+    pub fn effective_tax_rate(&self) -> f64 {
+        if self.gross_profit > 0 {
+            self.total_tax as f64 / self.gross_profit as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Average number of days between consecutive invoices, `None` with fewer than two of them.
+    pub fn average_interval(&self) -> Option<f64> {
+        const MILLIS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+        if self.count < 2 {
+            return None;
+        }
+
+        // Consecutive intervals of the sorted dates add up to the span between the first and last
+        Some((self.last_date? - self.first_date?) as f64 / MILLIS_PER_DAY / (self.count - 1) as f64)
+    }
+}
+
+/// Totals of the invoices dated in the twelve months up to `to`, whatever calendar year they are in.
+#[derive(Debug, Clone)]
+pub struct TrailingTotals {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub totals: InvoiceTotals
+}
+
+impl TrailingTotals {
+    /// The 365 days up to and including `to`
+    pub fn new(to: NaiveDate) -> TrailingTotals {
+        TrailingTotals { from: to - Duration::days(364), to, totals: InvoiceTotals::default() }
+    }
+
+    /// Years whose files can hold invoices of the twelve months
+    pub fn years(&self) -> RangeInclusive<i32> {
+        self.from.year()..=self.to.year()
+    }
+
+    /// Adds `invoice` if it is dated in the twelve months
+    pub fn add(&mut self, invoice: &Invoice) {
+        let date = invoice.local_date().date_naive();
+        if date >= self.from && date <= self.to {
+            self.totals.add(invoice);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientRevenue {
+    pub client: String,
+    pub gross_profit: Cents,
+    pub net_profit: Cents,
+    /// Share of the total gross profit
+    pub share: f64
+}
+
+/// Clients ranked by gross profit, ties ordered by name. Invoices without a client are grouped
+/// under `(no client)`.
+pub fn top_clients(invoices: &[Invoice]) -> Vec<ClientRevenue> {
+    let mut clients: HashMap<String, (Cents, Cents)> = HashMap::new();
+    for invoice in invoices {
+        let client = if invoice.client.is_empty() { "(no client)" } else { &invoice.client };
+        let entry = clients.entry(client.to_string()).or_default();
+        entry.0 += invoice.gross_profit;
+        entry.1 += invoice.net_profit;
+    }
+
+    let total_gross_profit: Cents = invoices.iter().map(|record| record.gross_profit).sum();
+    let mut ranked: Vec<ClientRevenue> = clients
+        .into_iter()
+        .map(|(client, (gross_profit, net_profit))| ClientRevenue {
+            share: if total_gross_profit > 0 { gross_profit as f64 / total_gross_profit as f64 } else { 0.0 },
+            client,
+            gross_profit,
+            net_profit
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.gross_profit.cmp(&a.gross_profit).then_with(|| a.client.cmp(&b.client)));
+
+    ranked
+}
+
+/// How the gross profit of single invoices is spread, from the smallest invoice to the largest.
+#[derive(Debug, Clone)]
+pub struct GrossDistribution {
+    pub min: Cents,
+    pub first_quartile: Cents,
+    pub median: Cents,
+    pub third_quartile: Cents,
+    pub max: Cents,
+    /// Lower bound and invoice count of equally wide bins from `min` to `max`
+    pub histogram: Vec<(Cents, usize)>
+}
+
+/// Distribution of the gross profit per invoice over `bins` bins, `None` without invoices
+pub fn gross_distribution(invoices: &[Invoice], bins: usize) -> Result<Option<GrossDistribution>, InvoiceError> {
+    if bins == 0 {
+        return Err(InvoiceError::invalid("bins", "needs to be more than 0"));
+    }
+    let mut gross_profits: Vec<Cents> = invoices.iter().map(|record| record.gross_profit).collect();
+    gross_profits.sort();
+    let (Some(&min), Some(&max)) = (gross_profits.first(), gross_profits.last()) else {
+        return Ok(None);
+    };
+
+    // Bins are at least a cent wide, so a year of equal invoices ends up in the first one
+    let width = ((max - min) / bins as Cents).max(1);
+    let mut histogram: Vec<(Cents, usize)> = (0..bins).map(|bin| (min + bin as Cents * width, 0)).collect();
+    for gross_profit in &gross_profits {
+        let bin = (((gross_profit - min) / width) as usize).min(bins - 1);
+        histogram[bin].1 += 1;
+    }
+
+    Ok(Some(GrossDistribution {
+        min,
+        first_quartile: quantile(&gross_profits, 0.25),
+        median: quantile(&gross_profits, 0.5),
+        third_quartile: quantile(&gross_profits, 0.75),
+        max,
+        histogram
+    }))
+}
+
+/// Value at `q` of the sorted, non-empty `values`, interpolated between the two nearest ones
+fn quantile(values: &[Cents], q: f64) -> Cents {
+    let position = q * (values.len() - 1) as f64;
+    let (lower, upper) = (values[position.floor() as usize], values[position.ceil() as usize]);
+
+    lower + ((upper - lower) as f64 * position.fract()).round() as Cents
+}
+
+/// Every field of a single invoice, as `show` prints it.
+#[derive(Debug, Serialize)]
+pub struct InvoiceRecord {
+    pub name: String,
+    /// Local date and time the invoice was recorded at
+    pub date: String,
+    pub days_worked: f64,
+    pub overtime_days: f64,
+    pub overtime_multiplier: f64,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub daily_rate: Cents,
+    pub currency: String,
+    /// Daily rate in `currency` as entered, unknown for invoices recorded before it was kept
+    #[serde(serialize_with = "cents::serialize_optional_amount")]
+    pub original_daily_rate: Option<Cents>,
+    pub client: String,
+    pub client_vat_id: String,
+    pub estimated: bool,
+    pub paid_date: Option<NaiveDate>,
+    pub profile: String,
+    pub vat_rate: f64,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub vat: Cents,
+    pub reverse_charge: bool,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub gross_profit: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub net_profit: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub government_tax: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub social_contribution_tax: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_tax: Cents
+}
+
+pub fn invoice_record(invoice: &Invoice) -> InvoiceRecord {
+    InvoiceRecord {
+        name: invoice.name.clone(),
+        date: invoice.local_date().format("%Y-%m-%d %H:%M:%S").to_string(),
+        days_worked: invoice.days_worked,
+        overtime_days: invoice.overtime_days,
+        overtime_multiplier: invoice.overtime_multiplier,
+        daily_rate: invoice.daily_rate,
+        currency: invoice.currency.clone(),
+        original_daily_rate: invoice.original_daily_rate,
+        client: invoice.client.clone(),
+        client_vat_id: invoice.client_vat_id.clone(),
+        estimated: invoice.estimated,
+        paid_date: invoice.paid_date,
+        profile: invoice.profile.clone(),
+        vat_rate: invoice.vat_rate,
+        vat: invoice.vat,
+        reverse_charge: invoice.reverse_charge,
+        gross_profit: invoice.gross_profit,
+        net_profit: invoice.net_profit,
+        government_tax: invoice.government_tax,
+        social_contribution_tax: invoice.social_contribution_tax,
+        total_tax: invoice.total_tax
+    }
+}
+
+/// Writes `record` as one `key: value` line per field, amounts in the shown format
+pub fn write_invoice_record_text<W: Write>(
+    mut writer: W,
+    record: &InvoiceRecord,
+    money: &MoneyFormat
+) -> Result<(), InvoiceError> {
+    writeln!(writer, "name: {}", record.name)?;
+    writeln!(writer, "date: {}", record.date)?;
+    writeln!(writer, "days_worked: {}", record.days_worked)?;
+    writeln!(writer, "overtime_days: {}", record.overtime_days)?;
+    writeln!(writer, "overtime_multiplier: {}", record.overtime_multiplier)?;
+    writeln!(writer, "daily_rate: {}", money.money(record.daily_rate))?;
+    writeln!(writer, "currency: {}", record.currency)?;
+    writeln!(
+        writer,
+        "original_daily_rate: {}",
+        record.original_daily_rate.map_or(String::new(), |original| money.money_in(original, &record.currency))
+    )?;
+    writeln!(writer, "client: {}", record.client)?;
+    writeln!(writer, "client_vat_id: {}", record.client_vat_id)?;
+    writeln!(writer, "estimated: {}", record.estimated)?;
+    writeln!(writer, "paid_date: {}", record.paid_date.map_or(String::new(), |paid_date| paid_date.to_string()))?;
+    writeln!(writer, "profile: {}", record.profile)?;
+    writeln!(writer, "vat_rate: {}", record.vat_rate)?;
+    writeln!(writer, "vat: {}", money.money(record.vat))?;
+    writeln!(writer, "reverse_charge: {}", record.reverse_charge)?;
+    writeln!(writer, "gross_profit: {}", money.money(record.gross_profit))?;
+    writeln!(writer, "net_profit: {}", money.money(record.net_profit))?;
+    writeln!(writer, "government_tax: {}", money.money(record.government_tax))?;
+    writeln!(writer, "social_contribution_tax: {}", money.money(record.social_contribution_tax))?;
+    writeln!(writer, "total_tax: {}", money.money(record.total_tax))?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Tax parameters the figures of a report were computed with, so they can be reproduced.
+#[derive(Debug, Serialize)]
+pub struct TaxParameters {
+    /// Profile the invoices are taxed under, left out for the default regime
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub profile: String,
+    /// Brackets in effect over the year, the first period starting on its first day
+    pub brackets: Vec<BracketPeriod>,
+    pub social_contribution_fee: f64,
+    pub social_base: SocialBase,
+    /// Yearly social contribution the invoices are topped up to, 0 for none
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub minimum_social_contribution: Cents
+}
+
+/// Yearly overview handed to an accountant.
+#[derive(Debug, Serialize)]
+pub struct AccountantReport {
+    pub year: i32,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_gross_profit: Cents,
+    /// Gross profit less the expenses deducted from it
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_taxable_profit: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_net_profit: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_government_tax: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_social_contribution: Cents,
+    #[serde(serialize_with = "cents::serialize_amount")]
+    pub total_tax: Cents,
+    pub quarters: Vec<QuarterlySummary>,
+    pub brackets: Vec<BracketTotal>,
+    pub parameters: TaxParameters
+}
+
+pub fn accountant_report(year: i32, invoices: &[Invoice], expenses: &[Expense], rules: &TaxRules) -> AccountantReport {
+    AccountantReport {
+        year,
+        total_gross_profit: invoices.iter().map(|record| record.gross_profit).sum(),
+        total_taxable_profit: expense::taxable_profits(invoices, expenses).iter().sum(),
+        total_net_profit: invoices.iter().map(|record| record.net_profit).sum(),
+        total_government_tax: invoices.iter().map(|record| record.government_tax).sum(),
+        total_social_contribution: invoices.iter().map(|record| record.social_contribution_tax).sum(),
+        total_tax: invoices.iter().map(|record| record.total_tax).sum(),
+        quarters: quarterly_summary(invoices),
+        brackets: bracket_totals(year, invoices, expenses, &rules.brackets),
+        parameters: TaxParameters {
+            profile: rules.profile.clone(),
+            brackets: tax::year_bracket_periods(&rules.brackets, year),
+            social_contribution_fee: rules.social_contribution_fee,
+            social_base: rules.social_base,
+            minimum_social_contribution: rules.minimum_social_contribution
+        }
+    }
+}
+
+pub fn write_accountant_report_text<W: Write>(
+    mut writer: W,
+    report: &AccountantReport,
+    money: &MoneyFormat
+) -> Result<(), InvoiceError> {
+    writeln!(writer, "Accountant report {}", report.year)?;
+    writeln!(writer)?;
+    writeln!(writer, "Total gross profit: {}", money.money(report.total_gross_profit))?;
+    if report.total_taxable_profit != report.total_gross_profit {
+        writeln!(writer, "Total taxable profit: {}", money.money(report.total_taxable_profit))?;
+    }
+    writeln!(writer, "Total net profit: {}", money.money(report.total_net_profit))?;
+    writeln!(writer, "Total government tax: {}", money.money(report.total_government_tax))?;
+    writeln!(writer, "Total social contribution: {}", money.money(report.total_social_contribution))?;
+    writeln!(writer, "Total taxes: {}", money.money(report.total_tax))?;
+
+    writeln!(writer)?;
+    for quarter in &report.quarters {
+        writeln!(
+            writer,
+            "Q{}: gross {}, net {}, government tax {}, social contribution {}, taxes {}",
+            quarter.quarter,
+            money.money(quarter.gross_profit),
+            money.money(quarter.net_profit),
+            money.money(quarter.government_tax),
+            money.money(quarter.social_contribution_tax),
+            money.money(quarter.total_tax)
+        )?;
+    }
+
+    writeln!(writer)?;
+    for total in &report.brackets {
+        writeln!(
+            writer,
+            "Bracket {}%: {} taxed, government tax {}",
+            total.perc * 100.0, money.money(total.gross_profit), money.money(total.government_tax)
+        )?;
+    }
+
+    writeln!(writer)?;
+    if report.parameters.profile.is_empty() {
+        writeln!(writer, "Computed with:")?;
+    } else {
+        writeln!(writer, "Computed with profile {}:", report.parameters.profile)?;
+    }
+    // Only a year with a change of brackets needs to say from when each set applies
+    let dated = report.parameters.brackets.len() > 1;
+    let indent = if dated { "    " } else { "  " };
+    for period in &report.parameters.brackets {
+        if dated {
+            writeln!(writer, "  From {}:", period.from)?;
+        }
+        for tax_bucket in &period.buckets {
+            let perc = tax_bucket.perc * 100.0;
+            match (tax_bucket.from, tax_bucket.to) {
+                (Some(from), Some(to)) => writeln!(writer, "{}{}% from {} up to {}", indent, perc, from, to)?,
+                (None, Some(to)) => writeln!(writer, "{}{}% up to {}", indent, perc, to)?,
+                (Some(from), None) => writeln!(writer, "{}{}% above {}", indent, perc, from)?,
+                (None, None) => writeln!(writer, "{}{}% above", indent, perc)?,
+            }
+        }
+    }
+    let social_base = match report.parameters.social_base {
+        SocialBase::Gross => "gross profit".to_string(),
+        SocialBase::AfterIncomeTax => "profit after government tax".to_string(),
+        SocialBase::ShareOfGross(share) => format!("{}% of gross profit", share * 100.0),
+    };
+    writeln!(
+        writer,
+        "  Social contribution {}% of {}",
+        report.parameters.social_contribution_fee * 100.0, social_base
+    )?;
+    if report.parameters.minimum_social_contribution > 0 {
+        writeln!(
+            writer,
+            "  Social contribution at least {} a year",
+            money.money(report.parameters.minimum_social_contribution)
+        )?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn write_monthly_summary<W: Write>(writer: W, months: &[MonthlySummary]) -> Result<(), InvoiceError> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+
+    writer.write_record(["month", "gross_profit", "net_profit", "total_tax", "tax_reserve"])?;
+    for month in months {
+        writer.write_record([
+            month.month.to_string(),
+            cents::to_amount(month.gross_profit).to_string(),
+            cents::to_amount(month.net_profit).to_string(),
+            cents::to_amount(month.total_tax).to_string(),
+            cents::to_amount(month.tax_reserve).to_string(),
+        ])?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Stretches between `start` and `end` of more than `min_days` days without an invoice.
+///
+/// A gap runs from the day after an invoice up to the day before the next one, the edges of the
+/// period count as invoices so idle time at its start and end shows up too.
+pub fn invoice_gaps(
+    dates: &[NaiveDate],
+    start: NaiveDate,
+    end: NaiveDate,
+    min_days: i64
+) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut dates: Vec<NaiveDate> = dates.iter().copied().filter(|date| *date >= start && *date <= end).collect();
+    dates.sort();
+
+    let mut gaps = vec![];
+    let mut previous = start - Duration::days(1);
+    for date in dates.into_iter().chain([end + Duration::days(1)]) {
+        let idle_days = (date - previous).num_days() - 1;
+        if idle_days > min_days {
+            gaps.push((previous + Duration::days(1), date - Duration::days(1)));
+        }
+        previous = date;
+    }
+
+    gaps
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let next = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1).unwrap()
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1).unwrap()
+    };
+
+    (next - first).num_days() as u32
+}
+
+/// Estimates the date on which the income kept after setting `reserve_ratio` aside for taxes
+/// covers a full year of `monthly_expenses`.
+///
+/// Months before `today` use the recorded figures, the rest of the year is projected at the
+/// daily run rate observed so far. Returns `None` when the threshold isn't reached this year.
+pub fn break_even_date(
+    year: i32,
+    today: NaiveDate,
+    months: &[MonthlySummary],
+    reserve_ratio: f64,
+    monthly_expenses: Cents
+) -> Result<Option<NaiveDate>, InvoiceError> {
+    // A ratio of 1 or more keeps nothing, so not even the smallest costs would ever be covered
+    if !(0.0..1.0).contains(&reserve_ratio) {
+        return Err(InvoiceError::invalid("reserve-ratio", "needs to be at least 0 and below 1"));
+    } else if monthly_expenses < 0 {
+        return Err(InvoiceError::invalid("monthly-expenses", "can not be negative"));
+    }
+
+    let threshold = (monthly_expenses * 12) as f64;
+    if threshold == 0.0 {
+        return Ok(NaiveDate::from_ymd_opt(year, 1, 1));
+    }
+
+    let mut cumulative_kept = 0.0;
+
+    for month in months {
+        let kept = month.gross_profit as f64 * (1.0 - reserve_ratio);
+        if kept > 0.0 && cumulative_kept + kept >= threshold {
+            let days = days_in_month(year, month.month);
+            let fraction = (threshold - cumulative_kept) / kept;
+            let day = ((fraction * days as f64).ceil() as u32).clamp(1, days);
+            return Ok(NaiveDate::from_ymd_opt(year, month.month, day));
+        }
+        cumulative_kept += kept;
+    }
+
+    let days_elapsed = today.ordinal() as f64;
+    let daily_run_rate = cumulative_kept / days_elapsed;
+    if daily_run_rate <= 0.0 {
+        return Ok(None);
+    }
+
+    let days_needed = ((threshold - cumulative_kept) / daily_run_rate).ceil() as i64;
+    let date = today + Duration::days(days_needed);
+
+    Ok(Some(date).filter(|date| date.year() == year))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::tests::invoice;
+
+    fn summaries(gross_profits: &[Cents]) -> Vec<MonthlySummary> {
+        (1..=12)
+            .map(|month| MonthlySummary {
+                month,
+                gross_profit: gross_profits.get(month as usize - 1).copied().unwrap_or(0),
+                ..MonthlySummary::default()
+            })
+            .collect()
+    }
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn expense(date: NaiveDate, amount: Cents) -> Expense {
+        Expense { name: "laptop".to_string(), date, amount, deductible_share: 1.0, profile: String::new() }
+    }
+
+    #[test]
+    fn trailing_twelve_months_span_the_year_boundary() {
+        let mut trailing = TrailingTotals::new(ymd(2024, 3, 15));
+        assert_eq!(trailing.years(), 2023..=2024);
+
+        let dates = [(2023, 3, 16), (2023, 3, 17), (2023, 12, 31), (2024, 1, 1), (2024, 3, 15), (2024, 3, 16)];
+        for date in dates.map(|(year, month, day)| ymd(year, month, day)) {
+            trailing.add(&Invoice { total_tax: 100, ..invoice(date, 1_000) });
+        }
+        // 2024 is a leap year, the 365 days start on 2023-03-17
+        assert_eq!(trailing.totals.count, 4);
+        assert_eq!(trailing.totals.gross_profit, 4_000);
+        assert_eq!(trailing.totals.total_tax, 400);
+    }
+
+    #[test]
+    fn quantiles_interpolate_between_the_nearest_values() {
+        let values = [100, 200, 300, 400];
+        assert_eq!(quantile(&values, 0.25), 175);
+        assert_eq!(quantile(&values, 0.5), 250);
+        assert_eq!(quantile(&values, 0.75), 325);
+        assert_eq!(quantile(&values, 1.0), 400);
+        assert_eq!(quantile(&[700], 0.5), 700);
+    }
+
+    #[test]
+    fn distribution_of_invoice_sizes() {
+        let invoices: Vec<Invoice> =
+            [400, 100, 1_000, 300, 200].iter().map(|gross_profit| invoice(ymd(2024, 1, 1), *gross_profit)).collect();
+
+        let distribution = gross_distribution(&invoices, 3).unwrap().unwrap();
+        assert_eq!((distribution.min, distribution.first_quartile, distribution.median), (100, 200, 300));
+        assert_eq!((distribution.third_quartile, distribution.max), (400, 1_000));
+        // The largest invoice falls in the last bin rather than one past it
+        assert_eq!(distribution.histogram, vec![(100, 3), (400, 1), (700, 1)]);
+    }
+
+    #[test]
+    fn equal_invoices_fall_in_the_first_bin() {
+        let invoices = [invoice(ymd(2024, 1, 1), 500), invoice(ymd(2024, 2, 1), 500)];
+
+        let distribution = gross_distribution(&invoices, 4).unwrap().unwrap();
+        assert_eq!(distribution.histogram, vec![(500, 2), (501, 0), (502, 0), (503, 0)]);
+    }
+
+    #[test]
+    fn distribution_needs_a_bin() {
+        let err = gross_distribution(&[invoice(ymd(2024, 1, 1), 500)], 0).unwrap_err();
+        assert!(matches!(err, InvoiceError::InvalidInput { field: "bins", .. }));
+    }
+
+    #[test]
+    fn totals_without_invoices_have_no_nan() {
+        let totals = InvoiceTotals::default();
+        assert_eq!(totals.effective_tax_rate(), 0.0);
+        assert_eq!(totals.average_interval(), None);
+
+        let mut totals = InvoiceTotals::default();
+        totals.add(&invoice(ymd(2024, 3, 1), 0));
+        assert_eq!(totals.effective_tax_rate(), 0.0);
+        assert_eq!(totals.average_interval(), None);
+    }
+
+    #[test]
+    fn summaries_without_invoices_are_zero() {
+        let rules = Config::default().tax_rules(None).unwrap();
+
+        let months = monthly_summary(2024, &[], &[], &rules, false);
+        assert_eq!(months.len(), 12);
+        assert!(months.iter().all(|month| month.gross_profit == 0 && month.tax_reserve == 0));
+        assert!(quarterly_summary(&[]).iter().all(|quarter| quarter.total_tax == 0));
+        assert!(top_clients(&[]).is_empty());
+        assert!(gross_distribution(&[], 5).unwrap().is_none());
+    }
+
+    #[test]
+    fn bracket_totals_add_up_to_the_government_tax() {
+        let rules = Config::default().tax_rules(None).unwrap();
+        let mut invoices: Vec<Invoice> = (1..=12).map(|month| invoice(ymd(2024, month, 10), 456_789)).collect();
+        let expenses = [expense(ymd(2024, 4, 1), 123_456)];
+        tax::recalculate_year(&mut invoices, &expenses, &rules);
+
+        let totals = bracket_totals(2024, &invoices, &expenses, &rules.brackets);
+        let government_tax: Cents = invoices.iter().map(|invoice| invoice.government_tax).sum();
+        assert_eq!(totals.iter().map(|total| total.government_tax).sum::<Cents>(), government_tax);
+        // 54,814.68 gross less the expense reaches into the open top bracket
+        assert_eq!(totals.iter().filter(|total| total.gross_profit > 0).count(), 4);
+    }
+
+    #[test]
+    fn brackets_only_tax_what_the_expenses_leave() {
+        let rules = Config::default().tax_rules(None).unwrap();
+        // 10,000 deducted from the second invoice, the first one is dated before the expense
+        let invoices = [invoice(ymd(2024, 6, 1), 1_000_000), invoice(ymd(2024, 2, 1), 1_000_000)];
+        let expenses = [expense(ymd(2024, 3, 1), 1_000_000)];
+
+        let totals = bracket_totals(2024, &invoices, &expenses, &rules.brackets);
+        let taxed: Cents = totals.iter().map(|total| total.gross_profit).sum();
+        assert_eq!(taxed, 1_000_000);
+        assert_eq!(totals[0].government_tax, 250_000);
+    }
+
+    #[test]
+    fn reserve_leaves_out_the_deducted_income() {
+        let rules = Config::default().tax_rules(None).unwrap();
+        let invoices = [invoice(ymd(2024, 1, 15), 400_000), invoice(ymd(2024, 2, 15), 400_000)];
+        let expenses = [expense(ymd(2024, 1, 1), 400_000)];
+
+        let months = monthly_summary(2024, &invoices, &expenses, &rules, false);
+        let buckets = tax::default_tax_buckets();
+        assert_eq!(months[0].tax_reserve, 0);
+        assert_eq!(months[1].tax_reserve, recommended_tax_reserve(&buckets, &rules, 400_000, 400_000));
+    }
+
+    #[test]
+    fn expense_after_the_last_invoice_is_left_out_of_the_reports() {
+        let rules = Config::default().tax_rules(None).unwrap();
+        let invoices = [invoice(ymd(2024, 6, 1), 1_000_000)];
+        let expenses = [expense(ymd(2024, 12, 1), 1_000_000)];
+
+        let report = accountant_report(2024, &invoices, &expenses, &rules);
+        assert_eq!(report.total_taxable_profit, 1_000_000);
+        assert_eq!(report.brackets[0].gross_profit, 1_000_000);
+    }
+
+    #[test]
+    fn break_even_within_the_recorded_months() {
+        // 20k gross keeps 12k after a 40% reserve, exactly a year of 1k a month by the end of January
+        let months = summaries(&[2_000_000]);
+        let break_even = break_even_date(2026, ymd(2026, 3, 31), &months, 0.4, 100_000).unwrap();
+        assert_eq!(break_even, Some(ymd(2026, 1, 31)));
+
+        // Half of it is crossed halfway through the month
+        let break_even = break_even_date(2026, ymd(2026, 3, 31), &months, 0.4, 50_000).unwrap();
+        assert_eq!(break_even, Some(ymd(2026, 1, 16)));
+    }
+
+    #[test]
+    fn break_even_projected_at_the_run_rate() {
+        // 9k kept over the 90 days up to March 31 is 100 a day, the 3k left takes another 30 days
+        let months = summaries(&[600_000, 600_000, 600_000]);
+        let break_even = break_even_date(2026, ymd(2026, 3, 31), &months, 0.5, 100_000).unwrap();
+        assert_eq!(break_even, Some(ymd(2026, 4, 30)));
+
+        // Not reached before the end of the year
+        let break_even = break_even_date(2026, ymd(2026, 3, 31), &months, 0.5, 1_000_000).unwrap();
+        assert_eq!(break_even, None);
+
+        // Nothing earned yet, no run rate to project
+        let break_even = break_even_date(2026, ymd(2026, 3, 31), &summaries(&[]), 0.5, 100_000).unwrap();
+        assert_eq!(break_even, None);
+    }
+
+    #[test]
+    fn break_even_without_expenses_is_the_first_day() {
+        let break_even = break_even_date(2026, ymd(2026, 3, 31), &summaries(&[]), 0.5, 0).unwrap();
+        assert_eq!(break_even, Some(ymd(2026, 1, 1)));
+    }
+
+    #[test]
+    fn break_even_rejects_invalid_inputs() {
+        let months = summaries(&[600_000]);
+        assert!(break_even_date(2026, ymd(2026, 3, 31), &months, 1.0, 100_000).is_err());
+        assert!(break_even_date(2026, ymd(2026, 3, 31), &months, -0.1, 100_000).is_err());
+        assert!(break_even_date(2026, ymd(2026, 3, 31), &months, f64::NAN, 100_000).is_err());
+        assert!(break_even_date(2026, ymd(2026, 3, 31), &months, 0.5, -1).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use csv::StringRecord;
+use rusqlite::types::ValueRef;
+use rusqlite::{params_from_iter, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::error::InvoiceError;
+use crate::expense::Expense;
+use crate::store::InvoiceStore;
+use crate::storage::{self, CsvFormat, HEADERS, SCHEMA_VERSION};
+use crate::hook::OnWrite;
+use crate::{with_path, Invoice};
+
+/// SQLite type of each of the `HEADERS` columns, so the amounts can be summed in queries
+const COLUMN_TYPES: [&str; SCHEMA_VERSION] = [
+    "TEXT", "INTEGER", "REAL", "INTEGER", "TEXT",
+    "INTEGER", "INTEGER", "INTEGER",
+    "INTEGER", "INTEGER", "TEXT", "TEXT",
+    "TEXT", "TEXT", "TEXT", "REAL", "REAL",
+    "INTEGER", "REAL", "INTEGER", "TEXT"
+];
+
+impl From<rusqlite::Error> for InvoiceError {
+    fn from(err: rusqlite::Error) -> Self {
+        InvoiceError::Io(io::Error::other(err))
+    }
+}
+
+/// The invoices of all years in one SQLite database, a row per invoice with the columns of the CSV
+/// files, and the expenses in a table of their own.
+///
+/// Each year has a revision that every write bumps. A year is only rewritten when its revision is
+/// still the one this process read, so a change another process made in between isn't lost.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    path: PathBuf,
+    on_write: OnWrite,
+    /// Revision of each year when this process last read or wrote it
+    last_seen: Mutex<BTreeMap<i32, i64>>
+}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it or adding the columns it lacks. `on_write` runs
+    /// after each write.
+    pub fn open(path: &Path, on_write: OnWrite) -> Result<SqliteStore, InvoiceError> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(path)
+            .map_err(|err| with_path(io::Error::other(err), &path.display().to_string()))?;
+
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS invoices (year INTEGER NOT NULL, position INTEGER NOT NULL);
+            CREATE INDEX IF NOT EXISTS invoices_year ON invoices (year, position);
+            CREATE TABLE IF NOT EXISTS revisions (year INTEGER PRIMARY KEY, revision INTEGER NOT NULL);
+            CREATE TABLE IF NOT EXISTS expenses (
+                year INTEGER NOT NULL, name TEXT NOT NULL, date TEXT NOT NULL, amount_cents INTEGER NOT NULL,
+                deductible_share REAL NOT NULL, profile TEXT NOT NULL
+            );"
+        )?;
+        // Columns are only ever added, a database from an older version gets the new ones empty
+        let existing: Vec<String> = connection
+            .prepare("SELECT name FROM pragma_table_info('invoices')")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for (column, column_type) in HEADERS.iter().zip(COLUMN_TYPES) {
+            if !existing.iter().any(|existing| existing == column) {
+                connection.execute(&format!("ALTER TABLE invoices ADD COLUMN {} {}", column, column_type), [])?;
+            }
+        }
+
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+            path: path.to_path_buf(),
+            on_write,
+            last_seen: Mutex::new(BTreeMap::new())
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn revision(connection: &Connection, year: i32) -> Result<i64, InvoiceError> {
+        let revision = connection
+            .query_row("SELECT revision FROM revisions WHERE year = ?1", [year], |row| row.get(0))
+            .optional()?;
+
+        Ok(revision.unwrap_or(0))
+    }
+
+    /// Bumps the revision of `year` and records the new one as seen
+    fn bump_revision(&self, connection: &Connection, year: i32) -> Result<(), InvoiceError> {
+        let revision = Self::revision(connection, year)? + 1;
+        connection.execute(
+            "INSERT OR REPLACE INTO revisions (year, revision) VALUES (?1, ?2)",
+            [year as i64, revision]
+        )?;
+        self.last_seen.lock().unwrap_or_else(PoisonError::into_inner).insert(year, revision);
+
+        Ok(())
+    }
+
+    /// Row values of `invoice` as written to the CSV files, with empty amounts and dates stored as NULL
+    fn values(invoice: &Invoice) -> Vec<Option<String>> {
+        storage::to_record(invoice)
+            .into_iter()
+            .zip(COLUMN_TYPES)
+            .map(|(value, column_type)| Some(value).filter(|value| column_type == "TEXT" || !value.is_empty()))
+            .collect()
+    }
+
+    fn insert(connection: &Connection, year: i32, position: i64, invoice: &Invoice) -> Result<(), InvoiceError> {
+        let placeholders: Vec<String> = (3..HEADERS.len() + 3).map(|index| format!("?{}", index)).collect();
+        let sql = format!(
+            "INSERT INTO invoices (year, position, {}) VALUES (?1, ?2, {})",
+            HEADERS.join(", "), placeholders.join(", ")
+        );
+        let leading = [Some(year.to_string()), Some(position.to_string())];
+        connection.execute(&sql, params_from_iter(leading.into_iter().chain(Self::values(invoice))))?;
+
+        Ok(())
+    }
+}
+
+impl InvoiceStore for SqliteStore {
+    fn years(&self) -> Result<Vec<i32>, InvoiceError> {
+        let connection = self.connection();
+        let years = connection
+            .prepare("SELECT DISTINCT year FROM invoices ORDER BY year")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(years)
+    }
+
+    fn load(&self, year: i32) -> Result<Vec<Invoice>, InvoiceError> {
+        let connection = self.connection();
+        // Taken before reading, as the modified time of a CSV file is
+        let revision = Self::revision(&connection, year)?;
+        self.last_seen.lock().unwrap_or_else(PoisonError::into_inner).insert(year, revision);
+
+        let sql = format!("SELECT {} FROM invoices WHERE year = ?1 ORDER BY position", HEADERS.join(", "));
+        let mut statement = connection.prepare(&sql)?;
+        let mut rows = statement.query([year])?;
+
+        // Read back through the CSV parsing, so a value is checked the same way wherever it is stored
+        let columns: Vec<Option<usize>> = (0..HEADERS.len()).map(Some).collect();
+        let legacy_columns: Vec<Option<usize>> = vec![None; HEADERS.len()];
+        let mut invoices: Vec<Invoice> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut record = StringRecord::new();
+            for (index, column) in HEADERS.iter().enumerate() {
+                match row.get_ref(index)? {
+                    ValueRef::Null => record.push_field(""),
+                    ValueRef::Integer(value) => record.push_field(&value.to_string()),
+                    ValueRef::Real(value) => record.push_field(&value.to_string()),
+                    ValueRef::Text(value) => record.push_field(&String::from_utf8_lossy(value)),
+                    ValueRef::Blob(_) => {
+                        return Err(InvoiceError::Parse(format!(
+                            "invoice {} of {} in `{}`: {} is binary", invoices.len() + 1, year, self.location(), column
+                        )));
+                    }
+                }
+            }
+            invoices.push(storage::from_record(&record, &columns, &legacy_columns, CsvFormat::default())?);
+        }
+
+        Ok(invoices)
+    }
+
+    fn save(&self, year: i32, invoices: &[Invoice]) -> Result<(), InvoiceError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let last_seen = self.last_seen.lock().unwrap_or_else(PoisonError::into_inner).get(&year).copied();
+        let revision = Self::revision(&transaction, year)?;
+        if last_seen.is_some_and(|last_seen| last_seen != revision) {
+            return Err(InvoiceError::Validation(format!(
+                "the invoices of {} in `{}` changed underneath us, re-run", year, self.location()
+            )));
+        }
+
+        transaction.execute("DELETE FROM invoices WHERE year = ?1", [year])?;
+        for (position, invoice) in invoices.iter().enumerate() {
+            Self::insert(&transaction, year, position as i64, invoice)?;
+        }
+        self.bump_revision(&transaction, year)?;
+        transaction.commit()?;
+        self.on_write.written(&self.location());
+
+        Ok(())
+    }
+
+    fn append(&self, year: i32, invoice: &Invoice) -> Result<(), InvoiceError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let position: i64 = transaction.query_row(
+            "SELECT COALESCE(MAX(position) + 1, 0) FROM invoices WHERE year = ?1",
+            [year],
+            |row| row.get(0)
+        )?;
+        Self::insert(&transaction, year, position, invoice)?;
+        self.bump_revision(&transaction, year)?;
+        transaction.commit()?;
+        self.on_write.written(&self.location());
+
+        Ok(())
+    }
+
+    fn expense_years(&self) -> Result<Vec<i32>, InvoiceError> {
+        let connection = self.connection();
+        let years = connection
+            .prepare("SELECT DISTINCT year FROM expenses ORDER BY year")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(years)
+    }
+
+    fn load_expenses(&self, year: i32) -> Result<Vec<Expense>, InvoiceError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT name, date, amount_cents, deductible_share, profile FROM expenses WHERE year = ?1 ORDER BY rowid"
+        )?;
+        let rows = statement.query_map([year], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+        })?;
+
+        let mut expenses: Vec<Expense> = Vec::new();
+        for row in rows {
+            let (name, date, amount, deductible_share, profile) = row?;
+            let date = date.parse().map_err(|_| {
+                InvoiceError::Parse(format!("expense `{}` in `{}`: invalid date `{}`", name, self.location(), date))
+            })?;
+            expenses.push(Expense { name, date, amount, deductible_share, profile });
+        }
+
+        Ok(expenses)
+    }
+
+    fn append_expense(&self, year: i32, expense: &Expense) -> Result<(), InvoiceError> {
+        self.connection().execute(
+            "INSERT INTO expenses (year, name, date, amount_cents, deductible_share, profile)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                year, expense.name, expense.date.to_string(), expense.amount, expense.deductible_share, expense.profile
+            ]
+        )?;
+        self.on_write.written(&self.location());
+
+        Ok(())
+    }
+
+    fn location(&self) -> String {
+        std::fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone()).display().to_string()
+    }
+}
@@ -0,0 +1,857 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+use chrono::prelude::{DateTime, Local};
+use chrono::TimeZone;
+use csv::{ReaderBuilder, StringRecord, StringRecordsIntoIter, WriterBuilder};
+
+use crate::cents::{self, Cents};
+use crate::error::InvoiceError;
+use crate::expense::Expense;
+use crate::store::InvoiceStore;
+use crate::hook::OnWrite;
+use crate::{with_path, Invoice};
+
+pub(crate) const HEADERS: [&str; 21] = [
+    "name", "date", "days_worked", "daily_rate_cents", "currency",
+    "gross_profit_cents", "net_profit_cents", "government_tax_cents",
+    "social_contribution_tax_cents", "total_tax_cents", "client", "client_vat_id",
+    "estimated", "paid_date", "profile", "overtime_days", "overtime_multiplier",
+    "original_daily_rate_cents", "vat_rate", "vat_cents", "reverse_charge"
+];
+
+/// Version of the invoice file layout. Columns are only ever added, so it is the number of them.
+pub const SCHEMA_VERSION: usize = HEADERS.len();
+
+/// Decimal places the day counts and multipliers are stored with, enough for an hour of an 8-hour day
+const DECIMALS: usize = 4;
+
+/// Invoices read one CSV row at a time, see `invoice_rows`
+pub(crate) struct InvoiceRows<R> {
+    records: StringRecordsIntoIter<R>,
+    columns: Vec<Option<usize>>,
+    legacy_columns: Vec<Option<usize>>,
+    /// Number of columns in the header, which every row needs to have
+    width: usize,
+    format: CsvFormat
+}
+
+impl<R: Read> Iterator for InvoiceRows<R> {
+    type Item = Result<Invoice, InvoiceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A row of nothing but blanks, e.g. from a hand edit, holds no invoice and is skipped
+        let record = self.records.by_ref().find(|record| {
+            record.as_ref().map_or(true, |record| !record.iter().all(|field| field.trim().is_empty()))
+        })?;
+
+        Some(record.map_err(InvoiceError::from).and_then(|record| {
+            if record.len() != self.width {
+                let line = record.position().map_or(0, |position| position.line());
+                return Err(InvoiceError::Parse(format!(
+                    "line {}: has {} of the {} columns in the header, the row may have been cut short",
+                    line, record.len(), self.width
+                )));
+            }
+            from_record(&record, &self.columns, &self.legacy_columns, self.format)
+        }))
+    }
+}
+
+/// Field separator and decimal mark of CSV files exchanged with spreadsheets. The invoice files
+/// themselves always use commas and decimal points.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvFormat {
+    pub delimiter: u8,
+    pub decimal_comma: bool,
+    /// Character grouping the thousands of numbers, stripped when reading, e.g. `,` in `1,234.56`
+    pub thousands_separator: Option<char>,
+    /// Lines starting with `#` are comments, e.g. the banner of an export. Not in the invoice files,
+    /// where an invoice name may start with it.
+    pub comments: bool
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat { delimiter: b',', decimal_comma: false, thousands_separator: None, comments: false }
+    }
+}
+
+impl CsvFormat {
+    pub fn new(delimiter: char, decimal_comma: bool, thousands_separator: Option<char>) -> Result<Self, InvoiceError> {
+        let delimiter = u8::try_from(delimiter)
+            .ok()
+            .filter(|delimiter| delimiter.is_ascii() && *delimiter != b'"' && *delimiter != b'\n')
+            .ok_or_else(|| InvoiceError::Validation(format!("`{}` can not be used as CSV delimiter", delimiter)))?;
+        if decimal_comma && delimiter == b',' {
+            return Err(InvoiceError::Validation("a decimal comma needs another delimiter, e.g. `;`".to_string()));
+        }
+
+        // A separator that is also the delimiter is fine, spreadsheets quote such cells
+        let decimal_mark = if decimal_comma { ',' } else { '.' };
+        let invalid = |separator: &char| *separator == decimal_mark || separator.is_ascii_digit();
+        if let Some(separator) = thousands_separator.filter(invalid) {
+            return Err(InvoiceError::Validation(format!(
+                "`{}` can not separate thousands with `{}` as decimal mark", separator, decimal_mark
+            )));
+        }
+
+        Ok(CsvFormat { delimiter, decimal_comma, thousands_separator, comments: true })
+    }
+
+    /// `value` with the thousands separators left out and a decimal point, as `parse` takes it.
+    ///
+    /// `None` when a separator is misplaced, e.g. in `1,23.5`, so a value in another format is an
+    /// error rather than read as a different number. A space also stands for the non-breaking
+    /// spaces spreadsheets group with, and with a decimal comma and no separator configured
+    /// points are taken to group thousands as before.
+    fn number(&self, value: &str) -> Option<String> {
+        let value = value.trim();
+        let separator = self.thousands_separator.or(self.decimal_comma.then_some('.'));
+        let is_separator = |c: char| {
+            Some(c) == separator || (separator == Some(' ') && (c == '\u{a0}' || c == '\u{202f}'))
+        };
+        let decimal_mark = if self.decimal_comma { ',' } else { '.' };
+
+        let (integer, fraction) = match value.split_once(decimal_mark) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (value, None),
+        };
+        if fraction.is_some_and(|fraction| fraction.contains(is_separator)) {
+            return None;
+        }
+        if integer.contains(is_separator) {
+            let digits = integer.trim_start_matches(['-', '+']);
+            let mut groups = digits.split(is_separator);
+            let first = groups.next()?;
+            if first.is_empty() || first.len() > 3 || groups.any(|group| group.len() != 3) {
+                return None;
+            }
+        }
+
+        let mut number: String = integer.chars().filter(|c| !is_separator(*c)).collect();
+        if let Some(fraction) = fraction {
+            number.push('.');
+            number.push_str(fraction);
+        }
+
+        Some(number)
+    }
+}
+
+pub(crate) fn to_record(invoice: &Invoice) -> Vec<String> {
+    vec![
+        invoice.name.clone(),
+        invoice.date.to_string(),
+        format_decimal(invoice.days_worked),
+        invoice.daily_rate.to_string(),
+        invoice.currency.clone(),
+        invoice.gross_profit.to_string(),
+        invoice.net_profit.to_string(),
+        invoice.government_tax.to_string(),
+        invoice.social_contribution_tax.to_string(),
+        invoice.total_tax.to_string(),
+        invoice.client.clone(),
+        invoice.client_vat_id.clone(),
+        invoice.estimated.to_string(),
+        invoice.paid_date.map_or(String::new(), |paid_date| paid_date.to_string()),
+        invoice.profile.clone(),
+        format_decimal(invoice.overtime_days),
+        format_decimal(invoice.overtime_multiplier),
+        invoice.original_daily_rate.map_or(String::new(), |original| original.to_string()),
+        format_decimal(invoice.vat_rate),
+        invoice.vat.to_string(),
+        invoice.reverse_charge.to_string(),
+    ]
+}
+
+/// Writes a day count or multiplier rounded to `DECIMALS` places without trailing zeros, so a
+/// computed value like a split share of the days is written the same way on every run.
+pub(crate) fn format_decimal(value: f64) -> String {
+    let formatted = format!("{:.*}", DECIMALS, value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    if formatted == "-0" { "0".to_string() } else { formatted.to_string() }
+}
+
+fn write_invoices<W: Write>(writer: W, invoices: &[Invoice]) -> Result<(), InvoiceError> {
+    write_invoices_as(writer, invoices, CsvFormat::default(), true)
+}
+
+/// Writes `invoices` for a spreadsheet in `format`, only the days and multiplier have decimals to convert.
+/// Without `header` only the rows are written, e.g. to append them below an existing header.
+pub(crate) fn write_invoices_as<W: Write>(
+    writer: W,
+    invoices: &[Invoice],
+    format: CsvFormat,
+    header: bool
+) -> Result<(), InvoiceError> {
+    // With comments a field starting with `#` is quoted, so its row isn't read back as a comment
+    let mut writer = WriterBuilder::new()
+        .delimiter(format.delimiter)
+        .comment(format.comments.then_some(b'#'))
+        .from_writer(writer);
+
+    if header {
+        writer.write_record(HEADERS)?;
+    }
+    for invoice in invoices {
+        let mut record = to_record(invoice);
+        if format.decimal_comma {
+            for index in [2, 15, 16, 18] {
+                record[index] = record[index].replace('.', ",");
+            }
+        }
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Invoices of the rows of `reader` that could be read, with the error naming the line of each one that
+/// couldn't. Only a file that can't be read at all, e.g. one without a header, fails as a whole.
+pub fn read_invoices<R: Read>(reader: R) -> Result<(Vec<Invoice>, Vec<InvoiceError>), InvoiceError> {
+    let (mut invoices, mut errors) = (Vec::new(), Vec::new());
+    for row in invoice_rows(reader, CsvFormat::default())? {
+        match row {
+            Ok(invoice) => invoices.push(invoice),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    Ok((invoices, errors))
+}
+
+/// Invoices of `reader` parsed one row at a time, for going over a file without keeping it in memory
+pub(crate) fn invoice_rows<R: Read>(reader: R, format: CsvFormat) -> Result<InvoiceRows<R>, InvoiceError> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(format.delimiter)
+        .comment(format.comments.then_some(b'#'))
+        // Rows of the wrong length are reported by line in `next`, not as a bare CSV error
+        .flexible(true)
+        .from_reader(reader);
+
+    // Columns are looked up by header, so files written before a column was added still load
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let columns: Vec<Option<usize>> = HEADERS.iter().map(|header| column(header)).collect();
+    // Files from before amounts were stored in cents hold them in euros, without the suffix
+    let legacy_columns: Vec<Option<usize>> = HEADERS
+        .iter()
+        .map(|header| column(header.trim_end_matches("_cents")))
+        .collect();
+
+    Ok(InvoiceRows {
+        records: reader.into_records(),
+        columns,
+        legacy_columns,
+        width: headers.len(),
+        format
+    })
+}
+
+pub(crate) fn from_record(
+    record: &StringRecord,
+    columns: &[Option<usize>],
+    legacy_columns: &[Option<usize>],
+    format: CsvFormat
+) -> Result<Invoice, InvoiceError> {
+    let line = record.position().map_or(0, |position| position.line());
+    let field = |index: usize| columns[index].and_then(|column| record.get(column)).unwrap_or("");
+    let amount = |index: usize| -> Result<Cents, InvoiceError> {
+        if columns[index].is_some() {
+            return parse_number(field(index), format, HEADERS[index], line);
+        }
+        match legacy_columns[index].and_then(|column| record.get(column)) {
+            Some(value) => parse_number(value, format, HEADERS[index], line).map(cents::from_amount),
+            // The amounts computed from the others are left for `backfill` to fill in when a file lacks them
+            None if (5..=9).contains(&index) => Ok(0),
+            None => parse_number("", format, HEADERS[index], line)
+        }
+    };
+
+    Ok(Invoice {
+        name: field(0).to_string(),
+        date: parse_date(field(1), line)?,
+        days_worked: parse_number(field(2), format, HEADERS[2], line)?,
+        daily_rate: amount(3)?,
+        currency: field(4).to_string(),
+        gross_profit: amount(5)?,
+        net_profit: amount(6)?,
+        government_tax: amount(7)?,
+        social_contribution_tax: amount(8)?,
+        total_tax: amount(9)?,
+        client: field(10).to_string(),
+        client_vat_id: field(11).to_string(),
+        estimated: field(12) == "true",
+        paid_date: match field(13) {
+            "" => None,
+            paid_date => Some(parse_field(paid_date, HEADERS[13], line)?),
+        },
+        profile: field(14).to_string(),
+        // Files from before overtime was recorded have none, billed at the plain daily rate
+        overtime_days: match field(15) {
+            "" => 0.0,
+            overtime_days => parse_number(overtime_days, format, HEADERS[15], line)?,
+        },
+        overtime_multiplier: match field(16) {
+            "" => 1.0,
+            overtime_multiplier => parse_number(overtime_multiplier, format, HEADERS[16], line)?,
+        },
+        original_daily_rate: match field(17) {
+            "" => None,
+            original => Some(parse_number(original, format, HEADERS[17], line)?),
+        },
+        // Files from before VAT was recorded charged none
+        vat_rate: match field(18) {
+            "" => 0.0,
+            vat_rate => parse_number(vat_rate, format, HEADERS[18], line)?,
+        },
+        vat: match field(19) {
+            "" => 0,
+            _ => amount(19)?,
+        },
+        reverse_charge: field(20) == "true",
+    })
+}
+
+fn parse_field<T: FromStr>(value: &str, column: &str, line: u64) -> Result<T, InvoiceError> {
+    value.parse().map_err(|_| InvoiceError::Parse(format!("line {}: invalid {} `{}`", line, column, value)))
+}
+
+/// Parses a number cell written in `format`, the error names the value as it was written
+fn parse_number<T: FromStr>(value: &str, format: CsvFormat, column: &str, line: u64) -> Result<T, InvoiceError> {
+    format.number(value)
+        .and_then(|number| number.parse().ok())
+        .ok_or_else(|| InvoiceError::Parse(format!("line {}: invalid {} `{}`", line, column, value)))
+}
+
+/// Milliseconds since the epoch, rejected when out of chrono's range so `local_date` can't panic.
+///
+/// The files store milliseconds, but a hand-edited or generated file may hold seconds since the
+/// epoch or an RFC 3339 timestamp. Numbers below `SECONDS_BELOW` are taken as seconds: as
+/// milliseconds they would be in the first days of 1973, as seconds they reach to the year 5138.
+fn parse_date(value: &str, line: u64) -> Result<u128, InvoiceError> {
+    const SECONDS_BELOW: u128 = 100_000_000_000;
+
+    let date: u128 = match value.parse::<u128>() {
+        Ok(seconds) if seconds < SECONDS_BELOW => seconds * 1000,
+        Ok(millis) => millis,
+        Err(_) => match DateTime::parse_from_rfc3339(value) {
+            Ok(date) => u128::try_from(date.timestamp_millis()).map_err(|_| {
+                InvoiceError::Parse(format!("line {}: {} `{}` is before 1970", line, HEADERS[1], value))
+            })?,
+            Err(_) => parse_field(value, HEADERS[1], line)?,
+        },
+    };
+
+    i64::try_from(date)
+        .ok()
+        .and_then(|millis| Local.timestamp_millis_opt(millis).single())
+        .map(|_| date)
+        .ok_or_else(|| InvoiceError::Parse(format!("line {}: {} `{}` is out of range", line, HEADERS[1], value)))
+}
+
+/// The invoices of each year in `invoices_{year}.csv` under `dir`, the expenses in `expenses_{year}.csv`
+pub struct CsvStore {
+    dir: PathBuf,
+    on_write: OnWrite,
+    /// Modified time of each year file when this store last read or wrote it. A file rewritten from
+    /// what was read is checked against it, so a change another process made in between isn't lost.
+    last_seen: Mutex<BTreeMap<i32, SystemTime>>
+}
+
+impl CsvStore {
+    /// The files under `dir`, which is created on the first write, running `on_write` after each write
+    pub fn new(dir: PathBuf, on_write: OnWrite) -> CsvStore {
+        CsvStore { dir, on_write, last_seen: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub(crate) fn year_file_path(&self, year: i32) -> String {
+        self.dir.join(format!("invoices_{}.csv", year)).display().to_string()
+    }
+
+    fn expense_file_path(&self, year: i32) -> String {
+        self.dir.join(format!("expenses_{}.csv", year)).display().to_string()
+    }
+
+    /// Years that have an invoice file, oldest first
+    fn year_files(&self) -> Result<Vec<i32>, InvoiceError> {
+        self.prefixed_year_files("invoices_")
+    }
+
+    fn expense_year_files(&self) -> Result<Vec<i32>, InvoiceError> {
+        self.prefixed_year_files("expenses_")
+    }
+
+    /// Years of the `{prefix}{year}.csv` files under `dir`, oldest first
+    fn prefixed_year_files(&self, prefix: &str) -> Result<Vec<i32>, InvoiceError> {
+        let mut years: Vec<i32> = Vec::new();
+
+        if !self.dir.exists() {
+            return Ok(years);
+        }
+
+        for entry in std::fs::read_dir(&self.dir).map_err(|err| with_path(err, &self.dir.display().to_string()))? {
+            let file_name = entry?.file_name();
+            let year = file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_prefix(prefix))
+                .and_then(|file_name| file_name.strip_suffix(".csv"))
+                .and_then(|year| year.parse().ok());
+            if let Some(year) = year {
+                years.push(year);
+            }
+        }
+        years.sort();
+
+        Ok(years)
+    }
+
+    fn for_each_year_file_invoice(
+        &self,
+        year: i32,
+        visit: &mut dyn FnMut(Result<Invoice, InvoiceError>)
+    ) -> Result<(), InvoiceError> {
+        let file_path = self.year_file_path(year);
+
+        if !Path::new(&file_path).exists() {
+            return Ok(());
+        }
+
+        // Nothing is written back, so a report can go on without the rows that couldn't be read
+        for invoice in invoice_rows(File::open(&file_path)?, CsvFormat::default())? {
+            visit(invoice.map_err(|err| InvoiceError::Validation(format!("{}, {}", file_path, err))));
+        }
+
+        Ok(())
+    }
+
+    /// The invoices of `year`, none when it has no file. Reading never writes, see `append_to_year_file`.
+    fn read_year_file(&self, year: i32) -> Result<Vec<Invoice>, InvoiceError> {
+        let file_path = self.year_file_path(year);
+
+        if !Path::new(&file_path).exists() {
+            return Ok(Vec::new());
+        }
+
+        // Taken before reading, so a change made while reading is caught too
+        self.remember_modified(year);
+        let file = File::open(&file_path)?;
+        let (invoices, errors) = read_invoices(file)?;
+        // The year is written back whole, which would drop the rows that couldn't be read
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors.iter().map(InvoiceError::to_string).collect();
+            return Err(InvoiceError::Parse(format!(
+                "{} has rows that can not be read, fix them first: {}",
+                file_path, errors.join("; ")
+            )));
+        }
+
+        Ok(invoices)
+    }
+
+    /// Rewrites the whole file of `year`, keeping a `.bak` copy of what was there before.
+    ///
+    /// The invoices are written to a temporary file first and moved over the old one, so an
+    /// interrupted write never leaves a half-written file behind.
+    fn write_year_file(&self, year: i32, invoices: &[Invoice]) -> Result<(), InvoiceError> {
+        let file_path = self.year_file_path(year);
+        let tmp_path = format!("{}.tmp", file_path);
+
+        let last_seen = self.last_seen.lock().unwrap_or_else(PoisonError::into_inner).get(&year).copied();
+        if last_seen.is_some() && last_seen != self.file_modified(year) {
+            return Err(InvoiceError::Validation(format!("`{}` changed underneath us, re-run", file_path)));
+        }
+
+        if Path::new(&file_path).exists() {
+            std::fs::copy(&file_path, format!("{}.bak", file_path))?;
+        }
+        self.create_dir()?;
+
+        write_invoices(File::create(&tmp_path)?, invoices)?;
+        std::fs::rename(&tmp_path, &file_path)?;
+        self.remember_modified(year);
+        self.on_write.written(&file_path);
+
+        Ok(())
+    }
+
+    fn append_to_year_file(&self, year: i32, invoice: &Invoice) -> Result<(), InvoiceError> {
+        let file_path = self.year_file_path(year);
+
+        // A new file gets its header here, and files from before a column was added are brought up to
+        // date first so the row lines up. An empty file, e.g. one cut short, has no header either.
+        let invoices = self.read_year_file(year)?;
+        let up_to_date = Path::new(&file_path).exists()
+            && ReaderBuilder::new().from_path(&file_path)?.headers()? == HEADERS.as_slice();
+        if !up_to_date {
+            self.write_year_file(year, &invoices)?;
+        }
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&file_path)?;
+
+        let mut writer = WriterBuilder::new().from_writer(file);
+
+        writer.write_record(to_record(invoice))?;
+        writer.flush()?;
+        self.remember_modified(year);
+        self.on_write.written(&file_path);
+
+        Ok(())
+    }
+
+    fn read_expense_file(&self, year: i32) -> Result<Vec<Expense>, InvoiceError> {
+        let file_path = self.expense_file_path(year);
+
+        if !Path::new(&file_path).exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut reader = ReaderBuilder::new().from_path(&file_path)?;
+        let expenses = reader.deserialize().collect::<Result<Vec<Expense>, csv::Error>>()?;
+
+        Ok(expenses)
+    }
+
+    /// Appends `expense` to the expense file of `year`, which gets its header when it is created
+    fn append_to_expense_file(&self, year: i32, expense: &Expense) -> Result<(), InvoiceError> {
+        let file_path = self.expense_file_path(year);
+        let exists = Path::new(&file_path).exists();
+
+        self.create_dir()?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file_path)
+            .map_err(|err| with_path(err, &file_path))?;
+
+        let mut writer = WriterBuilder::new().has_headers(!exists).from_writer(file);
+        writer.serialize(expense)?;
+        writer.flush()?;
+        self.on_write.written(&file_path);
+
+        Ok(())
+    }
+
+    fn create_dir(&self) -> Result<(), InvoiceError> {
+        std::fs::create_dir_all(&self.dir).map_err(|err| with_path(err, &self.dir.display().to_string()))?;
+        Ok(())
+    }
+
+    fn file_modified(&self, year: i32) -> Option<SystemTime> {
+        std::fs::metadata(self.year_file_path(year)).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Records the file of `year` as it is now as the one this process last saw
+    fn remember_modified(&self, year: i32) {
+        if let Some(modified) = self.file_modified(year) {
+            self.last_seen.lock().unwrap_or_else(PoisonError::into_inner).insert(year, modified);
+        }
+    }
+
+    /// Moves the file of `year` into `archive/`, named with the time it was archived, and says how to restore it
+    fn archive_year_file(&self, year: i32) -> Result<String, InvoiceError> {
+        let file_path = self.year_file_path(year);
+        let archive_dir = self.dir.join("archive");
+        std::fs::create_dir_all(&archive_dir).map_err(|err| with_path(err, &archive_dir.display().to_string()))?;
+        let archive_path = archive_dir
+            .join(format!("invoices_{}_{}.csv", year, Local::now().format("%Y%m%d%H%M%S")))
+            .display()
+            .to_string();
+        if Path::new(&archive_path).exists() {
+            return Err(InvoiceError::Validation(format!("`{}` already exists, try again in a second", archive_path)));
+        }
+        std::fs::rename(&file_path, &archive_path).map_err(|err| with_path(err, &file_path))?;
+        self.on_write.written(&archive_path);
+
+        Ok(format!("Archived {} to {}\nMove it back to {} to restore it", year, archive_path, file_path))
+    }
+
+    /// Number of invoices in the file of `year` and whether it is on an older layout
+    fn describe_year_file(&self, year: i32) -> Result<String, InvoiceError> {
+        let file_path = self.year_file_path(year);
+        let (invoices, errors) = read_invoices(File::open(&file_path)?)?;
+        let mut reader = ReaderBuilder::new().from_path(&file_path)?;
+        let outdated = reader.headers()? != HEADERS.as_slice();
+
+        Ok(format!(
+            "{}: {} invoices{}{}",
+            file_path,
+            invoices.len(),
+            match errors.len() {
+                0 => String::new(),
+                unreadable => format!(", {} rows that can not be read", unreadable),
+            },
+            if outdated { ", older layout, brought up to date when next written" } else { "" }
+        ))
+    }
+}
+
+impl InvoiceStore for CsvStore {
+    fn years(&self) -> Result<Vec<i32>, InvoiceError> {
+        self.year_files()
+    }
+
+    fn load(&self, year: i32) -> Result<Vec<Invoice>, InvoiceError> {
+        self.read_year_file(year)
+    }
+
+    fn save(&self, year: i32, invoices: &[Invoice]) -> Result<(), InvoiceError> {
+        self.write_year_file(year, invoices)
+    }
+
+    fn append(&self, year: i32, invoice: &Invoice) -> Result<(), InvoiceError> {
+        self.append_to_year_file(year, invoice)
+    }
+
+    fn expense_years(&self) -> Result<Vec<i32>, InvoiceError> {
+        self.expense_year_files()
+    }
+
+    fn load_expenses(&self, year: i32) -> Result<Vec<Expense>, InvoiceError> {
+        self.read_expense_file(year)
+    }
+
+    fn append_expense(&self, year: i32, expense: &Expense) -> Result<(), InvoiceError> {
+        self.append_to_expense_file(year, expense)
+    }
+
+    fn for_each(&self, year: i32, visit: &mut dyn FnMut(Result<Invoice, InvoiceError>)) -> Result<(), InvoiceError> {
+        self.for_each_year_file_invoice(year, visit)
+    }
+
+    fn archive(&self, year: i32) -> Result<String, InvoiceError> {
+        self.archive_year_file(year)
+    }
+
+    fn describe(&self, year: i32) -> Result<String, InvoiceError> {
+        self.describe_year_file(year)
+    }
+
+    fn location(&self) -> String {
+        std::fs::canonicalize(&self.dir)
+            .unwrap_or_else(|_| self.dir.clone())
+            .display()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{csv_store, in_temp_dir, invoice, ymd};
+
+    #[test]
+    fn year_files_get_exactly_one_header() {
+        in_temp_dir("headers", || {
+            let store = csv_store();
+            let header = HEADERS.join(",");
+            let lines = |year: i32| -> Vec<String> {
+                std::fs::read_to_string(store.year_file_path(year)).unwrap().lines().map(str::to_string).collect()
+            };
+            let appended = |year: i32| store.append_to_year_file(year, &invoice(ymd(year, 3, 1), 100_000)).unwrap();
+
+            // First write of a year without a file
+            appended(2031);
+            assert_eq!(lines(2031).iter().filter(|line| **line == header).count(), 1);
+            assert_eq!(lines(2031).len(), 2);
+
+            // Reading a year without a file leaves it without one
+            assert!(store.read_year_file(2032).unwrap().is_empty());
+            assert!(!Path::new(&store.year_file_path(2032)).exists());
+            appended(2032);
+            assert_eq!(lines(2032).iter().filter(|line| **line == header).count(), 1);
+            assert_eq!(lines(2032).len(), 2);
+
+            // Write to a file that already has invoices
+            appended(2033);
+            appended(2033);
+            assert_eq!(lines(2033)[0], header);
+            assert_eq!(lines(2033).iter().filter(|line| **line == header).count(), 1);
+            assert_eq!(store.read_year_file(2033).unwrap().len(), 2);
+        });
+    }
+
+    #[test]
+    fn file_changed_since_it_was_read_is_not_overwritten() {
+        in_temp_dir("changed", || {
+            let store = csv_store();
+            let invoices = [invoice(ymd(2034, 3, 1), 100_000)];
+            store.read_year_file(2034).unwrap();
+            store.write_year_file(2034, &invoices).unwrap();
+
+            // Another process appends a row after this one read the file
+            let mut file = OpenOptions::new().append(true).open(store.year_file_path(2034)).unwrap();
+            write_invoices_as(&mut file, &[invoice(ymd(2034, 4, 1), 1)], CsvFormat::default(), false).unwrap();
+            file.set_modified(SystemTime::now() + std::time::Duration::from_secs(10)).unwrap();
+
+            let err = store.write_year_file(2034, &invoices).unwrap_err();
+            assert_eq!(err.to_string(), format!("`{}` changed underneath us, re-run", store.year_file_path(2034)));
+            assert_eq!(store.read_year_file(2034).unwrap().len(), 2);
+
+            // Read again, the change is seen and the file can be written
+            store.write_year_file(2034, &invoices).unwrap();
+        });
+    }
+
+    #[test]
+    fn dates_are_read_as_seconds_millis_or_rfc_3339() {
+        // 2024-03-01T00:00:00Z in each form
+        assert_eq!(parse_date("1709251200", 2).unwrap(), 1_709_251_200_000);
+        assert_eq!(parse_date("1709251200000", 2).unwrap(), 1_709_251_200_000);
+        assert_eq!(parse_date("2024-03-01T00:00:00Z", 2).unwrap(), 1_709_251_200_000);
+        assert_eq!(parse_date("2024-03-01T01:00:00+01:00", 2).unwrap(), 1_709_251_200_000);
+    }
+
+    #[test]
+    fn seconds_and_millis_are_told_apart_by_magnitude() {
+        // The largest number taken as seconds is in the year 5138, the smallest as millis in 1973
+        assert_eq!(parse_date("99999999999", 2).unwrap(), 99_999_999_999_000);
+        assert_eq!(parse_date("100000000000", 2).unwrap(), 100_000_000_000);
+    }
+
+    #[test]
+    fn dates_that_can_not_be_read_are_rejected_with_their_line() {
+        assert!(parse_date("1969-12-31T00:00:00Z", 4).unwrap_err().to_string().contains("before 1970"));
+        assert!(parse_date("yesterday", 4).unwrap_err().to_string().starts_with("line 4: "));
+        let out_of_range = parse_date(&u128::MAX.to_string(), 4).unwrap_err();
+        assert!(out_of_range.to_string().ends_with("is out of range"));
+    }
+
+    #[test]
+    fn computed_decimals_are_written_the_same_way_every_run() {
+        assert_eq!(format_decimal(0.1 + 0.2), "0.3");
+        assert_eq!(format_decimal(123.450_000_000_000_1), "123.45");
+        assert_eq!(format_decimal(2.0), "2");
+        assert_eq!(format_decimal(-0.000_000_1), "0");
+    }
+
+    #[test]
+    fn invoices_read_back_as_written() {
+        // A split share of the days is a computed value, e.g. a third of them
+        let written = Invoice {
+            days_worked: 10.0 / 3.0,
+            overtime_days: 0.1 + 0.2,
+            overtime_multiplier: 1.1,
+            vat_rate: 0.21,
+            vat: 33_333,
+            paid_date: Some(ymd(2024, 6, 1)),
+            ..invoice(ymd(2024, 5, 1), 158_730)
+        };
+
+        let mut csv = Vec::new();
+        write_invoices(&mut csv, std::slice::from_ref(&written)).unwrap();
+        let (read, errors) = read_invoices(csv.as_slice()).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(to_record(&read[0]), to_record(&written));
+
+        // Written again it is the same to the byte, so the file doesn't change without a reason
+        let mut again = Vec::new();
+        write_invoices(&mut again, &read).unwrap();
+        assert_eq!(again, csv);
+    }
+
+    #[test]
+    fn unreadable_rows_are_reported_and_the_others_kept() {
+        let csv = format!(
+            "{}\n{}\n,,,,,,,,,,,,,,,,,,,,\n{}\n{}\n",
+            HEADERS.join(","),
+            "2024-01,1704067200000,3,50000,EUR,150000,90000,40000,20000,60000,,,false,,,0,1,,0,0,false",
+            "2024-02,1706745600000,2,lots,EUR,100000,60000,25000,15000,40000,,,false,,,0,1,,0,0,false",
+            "2024-03,1709251200000,1,50000,EUR,50000,30000,12500,7500,20000,,,false,,,0,1,,0,0,false"
+        );
+
+        let (invoices, errors) = read_invoices(csv.as_bytes()).unwrap();
+        let names: Vec<&str> = invoices.iter().map(|invoice| invoice.name.as_str()).collect();
+        assert_eq!(names, ["2024-01", "2024-03"]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "line 4: invalid daily_rate_cents `lots`");
+    }
+
+    #[test]
+    fn semicolon_delimited_decimal_comma_rows_are_read() {
+        let csv = format!(
+            "# Exported from a spreadsheet\n{}\n{}\n",
+            HEADERS.join(";"),
+            "2024-01;1704067200000;1,5;50000;EUR;75.000;45000;18750;11250;30000;;;false;;;0,5;1,25;;0,21;15750;false"
+        );
+        let format = CsvFormat::new(';', true, Some('.')).unwrap();
+
+        let invoices: Vec<Invoice> = invoice_rows(csv.as_bytes(), format)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].days_worked, 1.5);
+        assert_eq!(invoices[0].overtime_days, 0.5);
+        assert_eq!(invoices[0].overtime_multiplier, 1.25);
+        assert_eq!(invoices[0].vat_rate, 0.21);
+        assert_eq!(invoices[0].gross_profit, 75_000);
+    }
+
+    #[test]
+    fn decimal_comma_rows_read_back_as_written() {
+        let format = CsvFormat::new(';', true, None).unwrap();
+        let written = Invoice { days_worked: 2.5, ..invoice(ymd(2024, 5, 1), 125_000) };
+
+        let mut csv = Vec::new();
+        write_invoices_as(&mut csv, std::slice::from_ref(&written), format, true).unwrap();
+        assert!(String::from_utf8(csv.clone()).unwrap().contains(";2,5;"));
+
+        let read: Vec<Invoice> =
+            invoice_rows(csv.as_slice(), format).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read[0].days_worked, written.days_worked);
+        assert_eq!(read[0].gross_profit, written.gross_profit);
+    }
+
+    /// `value` read as an amount in `format`
+    fn amount(format: CsvFormat, value: &str) -> Option<f64> {
+        format.number(value)?.parse().ok()
+    }
+
+    #[test]
+    fn thousands_separators_of_the_common_formats_are_stripped() {
+        let comma = CsvFormat::new(';', false, Some(',')).unwrap();
+        let space = CsvFormat::new(';', true, Some(' ')).unwrap();
+        let apostrophe = CsvFormat::new(';', false, Some('\'')).unwrap();
+
+        assert_eq!(amount(comma, "1,234.56"), Some(1234.56));
+        assert_eq!(amount(comma, "-1,234,567"), Some(-1_234_567.0));
+        assert_eq!(amount(space, "1 234,56"), Some(1234.56));
+        // Spreadsheets group with non-breaking spaces
+        assert_eq!(amount(space, "1\u{a0}234,56"), Some(1234.56));
+        assert_eq!(amount(space, "12\u{202f}345"), Some(12_345.0));
+        assert_eq!(amount(apostrophe, "1'234.5"), Some(1234.5));
+        // Without a separator configured a decimal comma comes with points grouping the thousands
+        assert_eq!(amount(CsvFormat::new(';', true, None).unwrap(), "1.234,56"), Some(1234.56));
+        assert_eq!(amount(CsvFormat::default(), "1234.56"), Some(1234.56));
+    }
+
+    #[test]
+    fn misplaced_separators_are_rejected() {
+        let comma = CsvFormat::new(';', false, Some(',')).unwrap();
+
+        assert_eq!(comma.number("1,23.5"), None);
+        assert_eq!(comma.number("1234,567"), None);
+        assert_eq!(comma.number(",234"), None);
+        assert_eq!(comma.number("1.234,5"), None);
+        assert_eq!(amount(comma, "12a"), None);
+        let err = parse_number::<f64>("1,23.5", comma, "days_worked", 7).unwrap_err();
+        assert_eq!(err.to_string(), "line 7: invalid days_worked `1,23.5`");
+    }
+
+    #[test]
+    fn decimal_comma_needs_another_delimiter() {
+        assert!(CsvFormat::new(',', true, None).is_err());
+        assert!(CsvFormat::new(';', true, Some(',')).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::error::InvoiceError;
+use crate::expense::Expense;
+use crate::Invoice;
+
+/// Where the invoices and expenses of each year are kept, e.g. the yearly CSV files of `CsvStore`
+pub trait InvoiceStore {
+    /// Years that have invoices, oldest first
+    fn years(&self) -> Result<Vec<i32>, InvoiceError>;
+
+    /// All invoices of `year` in the order they were stored, none for a year without any
+    fn load(&self, year: i32) -> Result<Vec<Invoice>, InvoiceError>;
+
+    /// Replaces the invoices of `year` with `invoices`
+    fn save(&self, year: i32, invoices: &[Invoice]) -> Result<(), InvoiceError>;
+
+    /// Adds `invoice` after the invoices of `year`
+    fn append(&self, year: i32, invoice: &Invoice) -> Result<(), InvoiceError>;
+
+    /// Years that have expenses, oldest first
+    fn expense_years(&self) -> Result<Vec<i32>, InvoiceError>;
+
+    /// All expenses of `year` in the order they were recorded
+    fn load_expenses(&self, year: i32) -> Result<Vec<Expense>, InvoiceError>;
+
+    /// Adds `expense` after the expenses of `year`
+    fn append_expense(&self, year: i32, expense: &Expense) -> Result<(), InvoiceError>;
+
+    /// Passes each invoice of `year` to `visit`, a store that can read them one at a time need not load them all.
+    /// A row that can't be read is passed as its error and the rest still are.
+    fn for_each(&self, year: i32, visit: &mut dyn FnMut(Result<Invoice, InvoiceError>)) -> Result<(), InvoiceError> {
+        self.load(year)?.into_iter().map(Ok).for_each(visit);
+
+        Ok(())
+    }
+
+    /// Takes the invoices of `year` out of the counted ones, returning where they went for the user
+    fn archive(&self, year: i32) -> Result<String, InvoiceError> {
+        Err(InvoiceError::Validation(format!(
+            "{} can not be archived from {}, `export` it instead", year, self.location()
+        )))
+    }
+
+    /// One line about the invoices of `year`, for `version`
+    fn describe(&self, year: i32) -> Result<String, InvoiceError> {
+        Ok(format!("{}: {} invoices", year, self.load(year)?.len()))
+    }
+
+    /// Where the invoices are kept, for messages
+    fn location(&self) -> String;
+}
@@ -0,0 +1,525 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::cents::{self, Cents};
+use crate::config::{BracketPeriod, SocialBase, TaxRules};
+use crate::expense::{Deductions, Expense};
+use crate::Invoice;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaxBucket {
+    /// Where the bucket starts, which has to be where the one before it ends. Left out it is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+    pub perc: f64
+}
+
+/// Orders the invoices of a year by date and recomputes the taxes of those under the profile of
+/// `rules`, since each invoice is taxed on top of the taxable profit of all invoices before it.
+/// The `expenses` of the year are deducted from the invoices dated on or after them.
+pub fn recalculate_year(invoices: &mut [Invoice], expenses: &[Expense], rules: &TaxRules) {
+    invoices.sort_by_key(|invoice| invoice.date);
+
+    // Kept as a running total, summing all earlier invoices for each one is quadratic in the year
+    let mut prior_taxable_profit: Cents = 0;
+    let mut deductions = Deductions::new(expenses, &rules.profile);
+    for invoice in invoices.iter_mut() {
+        if invoice.profile != rules.profile {
+            continue;
+        }
+        invoice.vat = invoice.billed_vat();
+        let date = invoice.local_date().date_naive();
+        let tax_buckets = tax_buckets(&rules.brackets, date);
+        let deduction = deductions.take(date, invoice.billed_gross_profit());
+
+        let (gross_profit, net_profit, government_tax, social_contribution_tax) = calc_taxes(
+            invoice.billed_gross_profit(),
+            prior_taxable_profit,
+            deduction,
+            &tax_buckets,
+            rules
+        );
+        prior_taxable_profit += gross_profit - deduction;
+
+        invoice.gross_profit = gross_profit;
+        invoice.net_profit = net_profit;
+        invoice.government_tax = government_tax;
+        invoice.social_contribution_tax = social_contribution_tax;
+        invoice.total_tax = government_tax + social_contribution_tax;
+    }
+
+    apply_minimum_social_contribution(invoices, rules);
+}
+
+/// Tops the social contribution of the year up to the minimum of `rules`, spread over the
+/// invoices of its profile in proportion to their gross profit.
+///
+/// The minimum is owed on the year as a whole, so the whole year is recalculated with each invoice
+/// recorded while it applies, not only the new invoice taxed.
+fn apply_minimum_social_contribution(invoices: &mut [Invoice], rules: &TaxRules) {
+    let mut profile_invoices: Vec<&mut Invoice> = invoices
+        .iter_mut()
+        .filter(|invoice| invoice.profile == rules.profile)
+        .collect();
+
+    let social_contribution: Cents = profile_invoices.iter().map(|record| record.social_contribution_tax).sum();
+    let top_up = rules.minimum_social_contribution - social_contribution;
+    if top_up <= 0 || profile_invoices.is_empty() {
+        return;
+    }
+
+    let total_gross_profit: Cents = profile_invoices.iter().map(|record| record.gross_profit).sum();
+    let count = profile_invoices.len();
+    // The last invoice takes what rounding left over, so the shares add up to the top-up exactly
+    let mut remaining = top_up;
+    for (position, invoice) in profile_invoices.iter_mut().enumerate() {
+        let share = if position + 1 == count {
+            remaining
+        } else if total_gross_profit > 0 {
+            (top_up as i128 * invoice.gross_profit as i128 / total_gross_profit as i128) as Cents
+        } else {
+            top_up / count as Cents
+        };
+        remaining -= share;
+
+        invoice.social_contribution_tax += share;
+        invoice.total_tax += share;
+        invoice.net_profit -= share;
+    }
+}
+
+/// Brackets in effect on `date`, those of the last period started by then and the built-in
+/// ones before any configured period.
+pub fn tax_buckets(periods: &[BracketPeriod], date: NaiveDate) -> Vec<TaxBucket> {
+    periods
+        .iter()
+        .filter(|period| period.from <= date)
+        .max_by_key(|period| period.from)
+        .map_or_else(default_tax_buckets, |period| period.buckets.clone())
+}
+
+/// Bracket periods that apply to `year`, the first one starting on its first day.
+pub(crate) fn year_bracket_periods(periods: &[BracketPeriod], year: i32) -> Vec<BracketPeriod> {
+    let start = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+    let mut year_periods = vec![BracketPeriod { from: start, buckets: tax_buckets(periods, start) }];
+
+    let mut changes: Vec<&BracketPeriod> = periods
+        .iter()
+        .filter(|period| period.from > start && period.from.year() == year)
+        .collect();
+    changes.sort_by_key(|period| period.from);
+    year_periods.extend(changes.into_iter().cloned());
+
+    year_periods
+}
+
+/// Brackets used when the config has none for the date
+pub fn default_tax_buckets() -> Vec<TaxBucket> {
+    vec![
+        TaxBucket {
+            from: None,
+            to: Some(13_870),
+            perc: 0.25
+        },
+        TaxBucket {
+            from: None,
+            to: Some(24_480),
+            perc: 0.40
+        },
+        TaxBucket {
+            from: None,
+            to: Some(42_370),
+            perc: 0.45
+        },
+        TaxBucket {
+            from: None,
+            to: None,
+            perc: 0.5
+        }
+    ]
+}
+
+/// The `[from, to)` span of each bucket with its rate, in order. Each starts at its own floor or
+/// where the previous bucket ended.
+fn bucket_spans(tax_buckets: &[TaxBucket]) -> Vec<(Cents, Cents, f64)> {
+    let mut spans = vec![];
+
+    let mut previous_to = 0;
+    for tax_bucket in tax_buckets {
+        let from = tax_bucket.from.map_or(previous_to, |from| cents::from_amount(from as f64));
+        let to = tax_bucket.to.map_or(Cents::MAX, |to| cents::from_amount(to as f64));
+
+        spans.push((from, to, tax_bucket.perc));
+
+        previous_to = to;
+    }
+    spans
+}
+
+/// Splits `gross_profit`, earned on top of `total_gross_profit`, over the tax buckets it falls in.
+pub fn appliable_tax_buckets(
+    tax_buckets: &[TaxBucket],
+    total_gross_profit: Cents,
+    gross_profit: Cents
+) -> Vec<(Cents, f64)> {
+    let gross_profit_range = (total_gross_profit, total_gross_profit + gross_profit);
+
+    bucket_spans(tax_buckets)
+        .into_iter()
+        .map(|(from, to, perc)| (gross_profit_range.1.min(to) - gross_profit_range.0.max(from), perc))
+        .filter(|(overlap, _)| *overlap > 0)
+        .collect()
+}
+
+/// Government tax on the shares of `appliable_tax_buckets`, as (profit left after it, tax)
+pub fn calc_government_tax(appliable_tax_buckets: Vec<(Cents, f64)>) -> (Cents, Cents) {
+    let mut profit_after_government_tax = 0;
+    let mut government_tax = 0;
+
+    // Each bucket keeps what its own tax leaves, not what the tax of all buckets so far leaves
+    for (gross_profit, tax) in appliable_tax_buckets {
+        let bucket_tax = cents::apply_rate(gross_profit, tax);
+        government_tax += bucket_tax;
+        profit_after_government_tax += gross_profit - bucket_tax;
+    }
+
+    (profit_after_government_tax, government_tax)
+}
+
+/// Part of `gross_profit`, earned on top of `total_gross_profit`, that falls in the open-ended
+/// top bracket, with the rate of that bracket. `None` while the income stays below it.
+pub(crate) fn top_bracket_gross_profit(
+    tax_buckets: &[TaxBucket],
+    total_gross_profit: Cents,
+    gross_profit: Cents
+) -> Option<(Cents, f64)> {
+    // A single bucket is a flat rate, there is nothing to cross into
+    let [.., below_top, top] = tax_buckets else {
+        return None;
+    };
+    let top_from = cents::from_amount(top.from.or(below_top.to)? as f64);
+
+    if total_gross_profit + gross_profit <= top_from {
+        return None;
+    }
+    appliable_tax_buckets(tax_buckets, total_gross_profit, gross_profit).last().copied()
+}
+
+/// Amount the social contribution is computed on, depending on the regime
+pub(crate) fn social_contribution_base(
+    social_base: SocialBase,
+    gross_profit: Cents,
+    profit_after_government_tax: Cents
+) -> Cents {
+    match social_base {
+        SocialBase::Gross => gross_profit,
+        SocialBase::AfterIncomeTax => profit_after_government_tax,
+        SocialBase::ShareOfGross(share) => cents::apply_rate(gross_profit, share),
+    }
+}
+
+pub(crate) fn calc_social_contribution(profit_after_government_tax: Cents, base: Cents, fee: f64) -> (Cents, Cents) {
+    // A loss doesn't earn contributions back, so the base never goes below zero
+    let social_contribution = cents::apply_rate(base.max(0), fee);
+    let net_profit = profit_after_government_tax - social_contribution;
+    
+    (net_profit, social_contribution)
+}
+
+/// Share of the next taxable euro that goes to taxes once `taxable_profit` has been taxed,
+/// government tax of the bracket it falls in plus social contribution on its share of the base.
+pub fn marginal_tax_rate(tax_buckets: &[TaxBucket], rules: &TaxRules, taxable_profit: Cents) -> f64 {
+    let government_tax = bucket_spans(tax_buckets)
+        .into_iter()
+        .find(|(_, to, _)| taxable_profit < *to)
+        .map(|(_, _, perc)| perc)
+        .unwrap_or(0.0);
+
+    let social_share = match rules.social_base {
+        SocialBase::Gross => 1.0,
+        SocialBase::AfterIncomeTax => 1.0 - government_tax,
+        SocialBase::ShareOfGross(share) => share,
+    };
+
+    government_tax + social_share * rules.social_contribution_fee
+}
+
+/// Gross profit of the invoices taxed under `profile`, what the next invoice of it is taxed on top of
+pub(crate) fn profile_gross_profit(invoices: &[Invoice], profile: &str) -> Cents {
+    invoices.iter().filter(|record| record.profile == profile).map(|record| record.gross_profit).sum()
+}
+
+/// Taxable profit of the invoices taxed under `profile`, in date order, each less the part of it
+/// `deductions` covers. What the next invoice of it is taxed on top of.
+pub(crate) fn profile_taxable_profit(invoices: &[Invoice], profile: &str, deductions: &mut Deductions) -> Cents {
+    invoices
+        .iter()
+        .filter(|record| record.profile == profile)
+        .map(|record| record.gross_profit - deductions.take(record.local_date().date_naive(), record.gross_profit))
+        .sum()
+}
+
+/// Taxes on `gross_profit` on top of `prior_taxable_profit`, as (gross, net, government, social).
+///
+/// The `deduction` part of the gross profit is covered by expenses, only the rest is taxed.
+pub fn calc_taxes(
+    gross_profit: Cents,
+    prior_taxable_profit: Cents,
+    deduction: Cents,
+    tax_buckets: &[TaxBucket],
+    rules: &TaxRules
+) -> (Cents, Cents, Cents, Cents) {
+    let taxable_profit = gross_profit - deduction;
+    let appliable_tax_buckets = appliable_tax_buckets(tax_buckets, prior_taxable_profit, taxable_profit);
+
+    let (profit_after_government_tax, government_tax) = calc_government_tax(appliable_tax_buckets);
+    let base = social_contribution_base(rules.social_base, taxable_profit, profit_after_government_tax);
+    let (net_profit, social_contribution) =
+        calc_social_contribution(profit_after_government_tax, base, rules.social_contribution_fee);
+
+    (gross_profit, net_profit + deduction, government_tax, social_contribution)
+}
+
+/// Share of the gross profit that went to taxes, 0 when nothing was earned yet
+pub(crate) fn effective_tax_rate(invoices: &[Invoice]) -> f64 {
+    let total_gross_profit: Cents = invoices.iter().map(|record| record.gross_profit).sum();
+    let total_tax: Cents = invoices.iter().map(|record| record.total_tax).sum();
+
+    if total_gross_profit > 0 {
+        total_tax as f64 / total_gross_profit as f64
+    } else {
+        0.0
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::report;
+    use crate::tests::{invoice, ymd};
+
+    fn bucket(from: Option<u32>, to: Option<u32>, perc: f64) -> TaxBucket {
+        TaxBucket { from, to, perc }
+    }
+
+    /// Brackets of a single flat `perc` from `from` on
+    fn flat(from: NaiveDate, perc: f64) -> BracketPeriod {
+        BracketPeriod { from, buckets: vec![bucket(None, None, perc)] }
+    }
+
+    #[test]
+    fn brackets_in_effect_on_the_date_apply() {
+        let periods = [flat(ymd(2024, 1, 1), 0.25), flat(ymd(2024, 7, 1), 0.5)];
+
+        assert_eq!(tax_buckets(&periods, ymd(2023, 12, 31)).len(), default_tax_buckets().len());
+        assert_eq!(tax_buckets(&periods, ymd(2024, 6, 30))[0].perc, 0.25);
+        assert_eq!(tax_buckets(&periods, ymd(2024, 7, 1))[0].perc, 0.5);
+    }
+
+    #[test]
+    fn invoices_around_a_mid_year_change_are_taxed_by_their_own_brackets() {
+        let mut rules = Config::default().tax_rules(None).unwrap();
+        rules.brackets = vec![flat(ymd(2024, 1, 1), 0.25), flat(ymd(2024, 7, 1), 0.5)];
+        let mut invoices = [invoice(ymd(2024, 9, 1), 1_000_000), invoice(ymd(2024, 3, 1), 1_000_000)];
+
+        recalculate_year(&mut invoices, &[], &rules);
+        assert_eq!(invoices[0].government_tax, 250_000);
+        assert_eq!(invoices[1].government_tax, 500_000);
+    }
+
+    #[test]
+    fn income_inside_one_bucket_is_taxed_at_its_rate() {
+        let appliable = appliable_tax_buckets(&default_tax_buckets(), 0, 500_000);
+        assert_eq!(appliable, vec![(500_000, 0.25)]);
+
+        let (profit_after_government_tax, government_tax) = calc_government_tax(appliable);
+        assert_eq!(government_tax, 125_000);
+        assert_eq!(profit_after_government_tax, 375_000);
+    }
+
+    #[test]
+    fn income_straddling_a_boundary_is_split_at_it() {
+        // 10,000 earned before, the 8,000 on top cross the 13,870 boundary at 3,870
+        let appliable = appliable_tax_buckets(&default_tax_buckets(), 1_000_000, 800_000);
+        assert_eq!(appliable, vec![(387_000, 0.25), (413_000, 0.4)]);
+
+        let (profit_after_government_tax, government_tax) = calc_government_tax(appliable);
+        assert_eq!(government_tax, 96_750 + 165_200);
+        assert_eq!(profit_after_government_tax, 800_000 - 96_750 - 165_200);
+    }
+
+    #[test]
+    fn income_in_the_open_top_bucket_is_taxed_at_its_rate() {
+        let appliable = appliable_tax_buckets(&default_tax_buckets(), 5_000_000, 200_000);
+        assert_eq!(appliable, vec![(200_000, 0.5)]);
+
+        let (profit_after_government_tax, government_tax) = calc_government_tax(appliable);
+        assert_eq!(government_tax, 100_000);
+        assert_eq!(profit_after_government_tax, 100_000);
+    }
+
+    #[test]
+    fn cents_split_over_a_boundary_are_rounded_per_bucket() {
+        // One cent below 13,870 at 25%, two above it at 40%: 0.25 rounds down, 0.8 up
+        let appliable = appliable_tax_buckets(&default_tax_buckets(), 1_386_999, 3);
+        assert_eq!(appliable, vec![(1, 0.25), (2, 0.4)]);
+        assert_eq!(calc_government_tax(appliable), (2, 1));
+    }
+
+    #[test]
+    fn prior_income_on_a_boundary_starts_in_the_next_bucket() {
+        let appliable = appliable_tax_buckets(&default_tax_buckets(), 1_387_000, 100_000);
+        assert_eq!(appliable, vec![(100_000, 0.4)]);
+    }
+
+    #[test]
+    fn income_ending_on_a_boundary_stays_in_its_bucket() {
+        let appliable = appliable_tax_buckets(&default_tax_buckets(), 1_287_000, 100_000);
+        assert_eq!(appliable, vec![(100_000, 0.25)]);
+    }
+
+    #[test]
+    fn spans_start_at_the_floor_or_the_previous_ceiling() {
+        let tax_buckets = [
+            bucket(None, Some(10_000), 0.25),
+            bucket(Some(10_000), Some(20_000), 0.4),
+            bucket(None, None, 0.5)
+        ];
+
+        assert_eq!(
+            bucket_spans(&tax_buckets),
+            vec![(0, 1_000_000, 0.25), (1_000_000, 2_000_000, 0.4), (2_000_000, Cents::MAX, 0.5)]
+        );
+    }
+
+    /// Taxes of 10,000 at the 25% of the first default bracket, with a 20% social contribution on `social_base`
+    fn taxes_with_social_base(social_base: SocialBase) -> (Cents, Cents, Cents, Cents) {
+        let rules = Config::default().tax_rules(None).unwrap();
+        let rules = TaxRules { social_base, social_contribution_fee: 0.2, ..rules };
+        calc_taxes(1_000_000, 0, 0, &default_tax_buckets(), &rules)
+    }
+
+    #[test]
+    fn social_contribution_after_income_tax_by_default() {
+        let rules = Config::default().tax_rules(None).unwrap();
+        assert!(matches!(rules.social_base, SocialBase::AfterIncomeTax));
+        assert_eq!(taxes_with_social_base(SocialBase::AfterIncomeTax), (1_000_000, 600_000, 250_000, 150_000));
+    }
+
+    #[test]
+    fn social_contribution_on_gross() {
+        assert_eq!(taxes_with_social_base(SocialBase::Gross), (1_000_000, 550_000, 250_000, 200_000));
+    }
+
+    #[test]
+    fn social_contribution_on_a_share_of_gross() {
+        assert_eq!(taxes_with_social_base(SocialBase::ShareOfGross(0.5)), (1_000_000, 650_000, 250_000, 100_000));
+    }
+
+    /// Social contribution of each invoice of `gross_profits` recalculated with a 10% fee after income tax
+    /// and a yearly `minimum`
+    fn social_contributions_with_minimum(gross_profits: &[Cents], minimum: Cents) -> Vec<Cents> {
+        let rules = Config::default().tax_rules(None).unwrap();
+        let rules = TaxRules { social_contribution_fee: 0.1, minimum_social_contribution: minimum, ..rules };
+        let mut invoices: Vec<Invoice> = gross_profits
+            .iter()
+            .enumerate()
+            .map(|(index, gross_profit)| invoice(ymd(2024, index as u32 + 1, 1), *gross_profit))
+            .collect();
+
+        recalculate_year(&mut invoices, &[], &rules);
+        for invoice in &invoices {
+            assert_eq!(invoice.total_tax, invoice.government_tax + invoice.social_contribution_tax);
+            assert_eq!(invoice.net_profit, invoice.gross_profit - invoice.total_tax);
+        }
+        invoices.iter().map(|invoice| invoice.social_contribution_tax).collect()
+    }
+
+    #[test]
+    fn social_contribution_below_the_minimum_is_topped_up_by_gross_profit() {
+        // 7,500 and 22,500 cents of contribution, 20,000 short of the minimum
+        assert_eq!(social_contributions_with_minimum(&[100_000, 300_000], 50_000), vec![12_500, 37_500]);
+    }
+
+    #[test]
+    fn social_contribution_at_or_above_the_minimum_is_left_as_it_is() {
+        assert_eq!(social_contributions_with_minimum(&[100_000, 300_000], 30_000), vec![7_500, 22_500]);
+        assert_eq!(social_contributions_with_minimum(&[100_000, 300_000], 20_000), vec![7_500, 22_500]);
+    }
+
+    #[test]
+    fn top_up_shares_add_up_to_the_minimum_exactly() {
+        let contributions = social_contributions_with_minimum(&[100_000, 100_000, 100_000], 22_510);
+        assert_eq!(contributions, vec![7_503, 7_503, 7_504]);
+    }
+
+    #[test]
+    fn zero_rate_invoice_has_no_taxes() {
+        let rules = Config::default().tax_rules(None).unwrap();
+
+        assert_eq!(calc_taxes(0, 0, 0, &default_tax_buckets(), &rules), (0, 0, 0, 0));
+        assert_eq!(calc_taxes(0, 5_000_000, 0, &default_tax_buckets(), &rules), (0, 0, 0, 0));
+        assert!(appliable_tax_buckets(&default_tax_buckets(), 0, 0).is_empty());
+    }
+
+    #[test]
+    fn zero_rate_invoice_counts_its_days_but_no_revenue() {
+        let rules = Config::default().tax_rules(None).unwrap();
+        let pro_bono = Invoice { days_worked: 2.0, ..invoice(ymd(2024, 3, 1), 0) };
+        let mut invoices = [invoice(ymd(2024, 2, 1), 100_000), pro_bono, invoice(ymd(2024, 4, 1), 100_000)];
+
+        recalculate_year(&mut invoices, &[], &rules);
+        assert_eq!((invoices[1].gross_profit, invoices[1].total_tax, invoices[1].net_profit), (0, 0, 0));
+        // The invoice after it is taxed as if the pro-bono work wasn't there
+        assert_eq!(invoices[2].government_tax, invoices[0].government_tax);
+        assert!(invoices.iter().all(|invoice| invoice.check_invariants().is_ok()));
+
+        let mut totals = report::InvoiceTotals::default();
+        invoices.iter().for_each(|invoice| totals.add(invoice));
+        assert_eq!(totals.days_worked, 4.0);
+        assert_eq!(totals.gross_profit, 200_000);
+        assert!(totals.effective_tax_rate().is_finite());
+        assert!(effective_tax_rate(&invoices[1..2]).is_finite());
+    }
+
+    #[test]
+    fn no_social_contribution_on_zero_profit() {
+        assert_eq!(calc_social_contribution(0, 0, 0.2063), (0, 0));
+    }
+
+    #[test]
+    fn negative_profit_has_no_negative_social_contribution() {
+        // A credit note leaves the net as it is instead of adding a contribution back to it
+        assert_eq!(calc_social_contribution(-50_000, -50_000, 0.2063), (-50_000, 0));
+    }
+
+    #[test]
+    fn zero_rate_bucket_before_a_floor_is_not_taxed() {
+        // A reduced 10% only from 20k on, nothing between 10k and 20k
+        let tax_buckets =
+            [bucket(None, Some(10_000), 0.25), bucket(None, Some(20_000), 0.0), bucket(Some(20_000), None, 0.1)];
+
+        let appliable = appliable_tax_buckets(&tax_buckets, 0, 3_000_000);
+        assert_eq!(appliable, vec![(1_000_000, 0.25), (1_000_000, 0.0), (1_000_000, 0.1)]);
+
+        let (profit_after_government_tax, government_tax) = calc_government_tax(appliable);
+        assert_eq!(government_tax, 350_000);
+        assert_eq!(profit_after_government_tax, 2_650_000);
+    }
+
+    #[test]
+    fn marginal_rate_in_a_zero_rate_bucket_has_no_government_tax() {
+        let tax_buckets =
+            [bucket(None, Some(10_000), 0.25), bucket(None, Some(20_000), 0.0), bucket(Some(20_000), None, 0.1)];
+        let rules = Config::default().tax_rules(None).unwrap();
+        let social = rules.social_contribution_fee;
+
+        assert_eq!(marginal_tax_rate(&tax_buckets, &rules, 1_500_000), social);
+        assert_eq!(
+            marginal_tax_rate(&tax_buckets, &rules, 2_500_000),
+            0.1 + 0.9 * social
+        );
+    }
+}